@@ -0,0 +1,240 @@
+use crossterm::event::{KeyCode, KeyEvent};
+
+use crate::{
+    alarms::{Alarm, CHIME_URI, RECURRENCES},
+    keymap::{KeyAction, KeyContext, Keymap},
+    sonos::{SpeakerState, html_unescape},
+};
+
+/// The settings of an alarm that can be edited, in the order they're displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmField {
+    Room,
+    Time,
+    Recurrence,
+    Volume,
+    Source,
+    Enabled,
+}
+
+impl AlarmField {
+    pub const ALL: &[AlarmField] = &[
+        AlarmField::Room,
+        AlarmField::Time,
+        AlarmField::Recurrence,
+        AlarmField::Volume,
+        AlarmField::Source,
+        AlarmField::Enabled,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AlarmField::Room => "Room",
+            AlarmField::Time => "Time",
+            AlarmField::Recurrence => "Repeat",
+            AlarmField::Volume => "Volume",
+            AlarmField::Source => "Play",
+            AlarmField::Enabled => "Enabled",
+        }
+    }
+}
+
+/// Something an alarm can play: the chime or one of the favorites.
+#[derive(Debug, Clone)]
+struct AlarmSource {
+    label: String,
+    uri: String,
+    metadata: String,
+}
+
+/// What the main loop should do after a key was handled by the editor.
+pub enum EditorResult {
+    Continue,
+    Save(Alarm),
+    Cancel,
+}
+
+/// Form to create or edit an alarm, displayed on top of the alarms view.
+#[derive(Debug, Clone)]
+pub struct AlarmEditor {
+    pub alarm: Alarm,
+    pub field: AlarmField,
+    /// Digits typed into the time field so far, until there are enough of them
+    pub time_input: String,
+    /// UUID and name of the speakers the alarm can play on
+    rooms: Vec<(String, String)>,
+    sources: Vec<AlarmSource>,
+    recurrences: Vec<String>,
+}
+
+impl AlarmEditor {
+    pub fn new(alarm: Alarm, state: &SpeakerState) -> Self {
+        let mut rooms: Vec<_> = state
+            .rooms
+            .iter()
+            .map(|r| (r.uuid.clone(), r.name.clone()))
+            .collect();
+        // The speaker of an existing alarm may not be around any more
+        if !rooms.iter().any(|(uuid, _)| *uuid == alarm.room_uuid) {
+            rooms.push((alarm.room_uuid.clone(), alarm.room_uuid.clone()));
+        }
+
+        let mut sources = vec![AlarmSource {
+            label: "Chime".to_owned(),
+            uri: CHIME_URI.to_owned(),
+            metadata: String::new(),
+        }];
+        sources.extend(state.favorites.iter().map(|f| AlarmSource {
+            label: f.title.clone(),
+            uri: html_unescape(&f.uri),
+            metadata: html_unescape(&f.metadata),
+        }));
+        // Keep whatever the alarm plays, even if it was set up with something else than a
+        // favorite
+        if !sources.iter().any(|s| s.uri == alarm.program_uri) {
+            sources.push(AlarmSource {
+                label: alarm.source_label(),
+                uri: alarm.program_uri.clone(),
+                metadata: alarm.program_metadata.clone(),
+            });
+        }
+
+        let mut recurrences: Vec<String> = RECURRENCES.iter().map(|r| r.to_string()).collect();
+        if !recurrences.contains(&alarm.recurrence) {
+            recurrences.push(alarm.recurrence.clone());
+        }
+
+        Self {
+            alarm,
+            field: AlarmField::Room,
+            time_input: String::new(),
+            rooms,
+            sources,
+            recurrences,
+        }
+    }
+
+    pub fn is_new(&self) -> bool {
+        self.alarm.id.is_none()
+    }
+
+    /// The value of a field, as displayed in the form.
+    pub fn value(&self, field: AlarmField) -> String {
+        match field {
+            AlarmField::Room => self
+                .rooms
+                .iter()
+                .find(|(uuid, _)| *uuid == self.alarm.room_uuid)
+                .map_or_else(|| self.alarm.room_uuid.clone(), |(_, name)| name.clone()),
+            AlarmField::Time if !self.time_input.is_empty() => {
+                let mut time: String = format!("{:_<4}", self.time_input);
+                time.insert(2, ':');
+                time
+            }
+            AlarmField::Time => {
+                let (hours, minutes) = self.alarm.time();
+                format!("{hours:02}:{minutes:02}")
+            }
+            AlarmField::Recurrence => self.alarm.recurrence_label(),
+            AlarmField::Volume => self.alarm.volume.to_string(),
+            AlarmField::Source => self
+                .sources
+                .iter()
+                .find(|s| s.uri == self.alarm.program_uri)
+                .map_or_else(|| self.alarm.source_label(), |s| s.label.clone()),
+            AlarmField::Enabled => if self.alarm.enabled { "Yes" } else { "No" }.to_owned(),
+        }
+    }
+
+    pub fn handle_key(&mut self, key: &KeyEvent, keymap: &Keymap) -> EditorResult {
+        // The time is typed in, whatever the digits are bound to
+        if self.field == AlarmField::Time {
+            match key.code {
+                KeyCode::Backspace => {
+                    self.time_input.pop();
+                    return EditorResult::Continue;
+                }
+                KeyCode::Char(c) if c.is_ascii_digit() => {
+                    self.type_time(c);
+                    return EditorResult::Continue;
+                }
+                _ => {}
+            }
+        }
+
+        match keymap.action_for(KeyContext::AlarmEditor, key) {
+            Some(KeyAction::Cancel) => return EditorResult::Cancel,
+            Some(KeyAction::Save) => return EditorResult::Save(self.alarm.clone()),
+            Some(KeyAction::Up) => self.move_field(-1),
+            Some(KeyAction::Down) => self.move_field(1),
+            Some(KeyAction::Decrease) => self.change(-1),
+            Some(KeyAction::Increase) => self.change(1),
+            Some(KeyAction::Toggle) if self.field == AlarmField::Enabled => self.change(1),
+            _ => {}
+        }
+        EditorResult::Continue
+    }
+
+    fn move_field(&mut self, step: isize) {
+        // A time that was only partly typed is dropped
+        self.time_input.clear();
+        let index = AlarmField::ALL
+            .iter()
+            .position(|f| *f == self.field)
+            .unwrap_or(0);
+        let index = (index as isize + step).rem_euclid(AlarmField::ALL.len() as isize);
+        self.field = AlarmField::ALL[index as usize];
+    }
+
+    /// Change the value of the current field, e.g. to the next room.
+    fn change(&mut self, step: isize) {
+        let alarm = &mut self.alarm;
+        match self.field {
+            AlarmField::Room => {
+                let index = cycle(&self.rooms, |(uuid, _)| *uuid == alarm.room_uuid, step);
+                alarm.room_uuid = self.rooms[index].0.clone();
+            }
+            AlarmField::Time => {
+                self.time_input.clear();
+                let (hours, minutes) = alarm.time();
+                let minutes = (hours * 60 + minutes) as isize + step * 5;
+                let minutes = minutes.rem_euclid(24 * 60) as u32;
+                alarm.set_time(minutes / 60, minutes % 60);
+            }
+            AlarmField::Recurrence => {
+                let index = cycle(&self.recurrences, |r| *r == alarm.recurrence, step);
+                alarm.recurrence = self.recurrences[index].clone();
+            }
+            AlarmField::Volume => {
+                alarm.volume = (alarm.volume as isize + step * 5).clamp(0, 100) as u16;
+            }
+            AlarmField::Source => {
+                let index = cycle(&self.sources, |s| s.uri == alarm.program_uri, step);
+                let source = &self.sources[index];
+                alarm.program_uri = source.uri.clone();
+                alarm.program_metadata = source.metadata.clone();
+            }
+            AlarmField::Enabled => alarm.enabled = !alarm.enabled,
+        }
+    }
+
+    /// Times are typed as 4 digits, e.g. 0730.
+    fn type_time(&mut self, digit: char) {
+        self.time_input.push(digit);
+        if self.time_input.len() < 4 {
+            return;
+        }
+        let input = std::mem::take(&mut self.time_input);
+        let (hours, minutes) = input.split_at(2);
+        // Not a valid time: start again
+        if let (Ok(hours @ 0..24), Ok(minutes @ 0..60)) = (hours.parse(), minutes.parse()) {
+            self.alarm.set_time(hours, minutes);
+        }
+    }
+}
+
+/// Index of the item after (or before) the current one, wrapping around.
+fn cycle<T>(items: &[T], is_current: impl Fn(&T) -> bool, step: isize) -> usize {
+    let current = items.iter().position(is_current).unwrap_or(0);
+    (current as isize + step).rem_euclid(items.len().max(1) as isize) as usize
+}
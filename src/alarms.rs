@@ -0,0 +1,199 @@
+use anyhow::{Context, Result};
+use sonor::{Speaker, URN};
+
+use crate::sonos::{extract_tag_content, html_unescape, xml_escape};
+
+const ALARM_CLOCK: &URN = &URN::service("schemas-upnp-org", "AlarmClock", 1);
+
+/// Program of the alarms that play the built-in chime instead of some music
+pub const CHIME_URI: &str = "x-rincon-buzzer:0";
+
+/// How often an alarm can go off, in the order they're cycled through when editing.
+pub const RECURRENCES: &[&str] = &["ONCE", "DAILY", "WEEKDAYS", "WEEKENDS"];
+
+/// A wake-up alarm, as stored by the speakers. They're shared by the whole household.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alarm {
+    /// `None` for an alarm that hasn't been created yet
+    pub id: Option<String>,
+    /// Local time at which the alarm goes off, as HH:MM:SS
+    pub start_time: String,
+    /// How long the alarm plays for, as HH:MM:SS
+    pub duration: String,
+    /// One of `RECURRENCES`, or `ON_` followed by the days of the week (0 is Sunday)
+    pub recurrence: String,
+    pub enabled: bool,
+    /// Speaker the alarm plays on
+    pub room_uuid: String,
+    pub program_uri: String,
+    pub program_metadata: String,
+    pub play_mode: String,
+    pub volume: u16,
+    pub include_linked_zones: bool,
+}
+
+impl Alarm {
+    /// A new alarm with the same defaults as the Sonos app.
+    pub fn new(room_uuid: String) -> Self {
+        Self {
+            id: None,
+            start_time: "07:00:00".to_owned(),
+            duration: "01:00:00".to_owned(),
+            recurrence: "DAILY".to_owned(),
+            enabled: true,
+            room_uuid,
+            program_uri: CHIME_URI.to_owned(),
+            program_metadata: String::new(),
+            play_mode: "NORMAL".to_owned(),
+            volume: 20,
+            include_linked_zones: false,
+        }
+    }
+
+    /// Hours and minutes of the start time
+    pub fn time(&self) -> (u32, u32) {
+        let mut parts = self.start_time.split(':').map(|p| p.parse().unwrap_or(0));
+        (
+            parts.next().unwrap_or(0).min(23),
+            parts.next().unwrap_or(0).min(59),
+        )
+    }
+
+    pub fn set_time(&mut self, hours: u32, minutes: u32) {
+        self.start_time = format!("{hours:02}:{minutes:02}:00");
+    }
+
+    /// The recurrence in plain words, e.g. "Mon, Wed, Fri"
+    pub fn recurrence_label(&self) -> String {
+        recurrence_label(&self.recurrence)
+    }
+
+    /// What the alarm plays: the chime, or the title of the favorite it was set up with
+    pub fn source_label(&self) -> String {
+        if self.program_uri == CHIME_URI {
+            return "Chime".to_owned();
+        }
+        extract_tag_content(&self.program_metadata, "<dc:title>", "</dc:title>")
+            .map(html_unescape)
+            .unwrap_or_else(|| self.program_uri.clone())
+    }
+
+    /// Arguments common to `CreateAlarm` and `UpdateAlarm`
+    fn payload(&self) -> String {
+        format!(
+            r#"<StartLocalTime>{}</StartLocalTime>
+<Duration>{}</Duration>
+<Recurrence>{}</Recurrence>
+<Enabled>{}</Enabled>
+<RoomUUID>{}</RoomUUID>
+<ProgramURI>{}</ProgramURI>
+<ProgramMetaData>{}</ProgramMetaData>
+<PlayMode>{}</PlayMode>
+<Volume>{}</Volume>
+<IncludeLinkedZones>{}</IncludeLinkedZones>"#,
+            self.start_time,
+            self.duration,
+            self.recurrence,
+            u8::from(self.enabled),
+            self.room_uuid,
+            xml_escape(&self.program_uri),
+            xml_escape(&self.program_metadata),
+            self.play_mode,
+            self.volume,
+            u8::from(self.include_linked_zones)
+        )
+    }
+}
+
+pub fn recurrence_label(recurrence: &str) -> String {
+    const DAYS: &[&str] = &["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+    match recurrence {
+        "ONCE" => "Once".to_owned(),
+        "DAILY" => "Every day".to_owned(),
+        "WEEKDAYS" => "Weekdays".to_owned(),
+        "WEEKENDS" => "Weekends".to_owned(),
+        other => match other.strip_prefix("ON_") {
+            Some(days) => days
+                .chars()
+                .filter_map(|d| d.to_digit(10).and_then(|d| DAYS.get(d as usize)))
+                .copied()
+                .collect::<Vec<_>>()
+                .join(", "),
+            None => other.to_owned(),
+        },
+    }
+}
+
+/// All the alarms of the household, sorted by time.
+pub async fn list_alarms(speaker: &Speaker) -> Result<Vec<Alarm>> {
+    let response = speaker
+        .action(ALARM_CLOCK, "ListAlarms", "")
+        .await
+        .context("Failed to list the alarms")?;
+    let xml = response
+        .get("CurrentAlarmList")
+        .context("No CurrentAlarmList in response")?;
+
+    let mut alarms = parse_alarms(xml);
+    alarms.sort_by(|a, b| a.start_time.cmp(&b.start_time));
+    Ok(alarms)
+}
+
+/// Create the alarm if it doesn't have an ID yet, or else update it.
+pub async fn save_alarm(speaker: &Speaker, alarm: &Alarm) -> Result<()> {
+    match &alarm.id {
+        Some(id) => {
+            let payload = format!("<ID>{id}</ID>\n{}", alarm.payload());
+            speaker
+                .action(ALARM_CLOCK, "UpdateAlarm", &payload)
+                .await
+                .context("Failed to update the alarm")?;
+        }
+        None => {
+            speaker
+                .action(ALARM_CLOCK, "CreateAlarm", &alarm.payload())
+                .await
+                .context("Failed to create the alarm")?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn delete_alarm(speaker: &Speaker, id: &str) -> Result<()> {
+    speaker
+        .action(ALARM_CLOCK, "DestroyAlarm", &format!("<ID>{id}</ID>"))
+        .await
+        .context("Failed to delete the alarm")?;
+    Ok(())
+}
+
+fn parse_alarms(xml: &str) -> Vec<Alarm> {
+    // Each alarm is a single element, with everything in its attributes
+    xml.split("<Alarm ")
+        .skip(1)
+        // Keep the space before the first attribute, to find it like the other ones
+        .map(|element| format!(" {element}"))
+        .filter_map(|element| {
+            let attribute = |name| attribute(&element, name).map(html_unescape);
+            Some(Alarm {
+                id: Some(attribute("ID")?),
+                start_time: attribute("StartTime")?,
+                duration: attribute("Duration").unwrap_or_default(),
+                recurrence: attribute("Recurrence").unwrap_or_else(|| "DAILY".to_owned()),
+                enabled: attribute("Enabled").is_some_and(|e| e == "1"),
+                room_uuid: attribute("RoomUUID")?,
+                program_uri: attribute("ProgramURI").unwrap_or_else(|| CHIME_URI.to_owned()),
+                program_metadata: attribute("ProgramMetaData").unwrap_or_default(),
+                play_mode: attribute("PlayMode").unwrap_or_else(|| "NORMAL".to_owned()),
+                volume: attribute("Volume")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0),
+                include_linked_zones: attribute("IncludeLinkedZones").is_some_and(|i| i == "1"),
+            })
+        })
+        .collect()
+}
+
+fn attribute<'a>(element: &'a str, name: &str) -> Option<&'a str> {
+    extract_tag_content(element, &format!(" {name}=\""), "\"")
+}
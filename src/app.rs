@@ -1,88 +1,575 @@
-use std::{net::Ipv4Addr, str::FromStr};
+use std::{
+    ops::ControlFlow,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::{Result, anyhow};
-use clap::ArgMatches;
-use crossterm::event::{Event, EventStream};
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, MouseEvent, MouseEventKind};
 use futures::TryStreamExt;
-use ratatui::DefaultTerminal;
-use tokio::{select, sync::mpsc};
+use ratatui::{DefaultTerminal, layout::Rect};
+use ratatui_image::picker::Picker;
+use tokio::{
+    select,
+    sync::mpsc::{self, UnboundedSender},
+};
 use tracing::{debug, warn};
 
-use crate::{State, Update, input, sonos, view};
+use crate::{
+    Action, Notification, Request, State, Update, ViewMode,
+    alarm_editor::{AlarmEditor, EditorResult},
+    alarms::Alarm,
+    art::AlbumArt,
+    config::Config,
+    filter::Filter,
+    input,
+    keymap::{KeyAction, KeyContext, Keymap},
+    menu::{Menu, MenuResult, Prompt},
+    metrics, mqtt,
+    pending::Predictions,
+    sonos::{self, SpeakerState},
+    theme::Theme,
+    view,
+};
 
 pub struct App {
-    provided_ips: Vec<Ipv4Addr>,
-    provided_names: Vec<String>,
+    config: Config,
+    keymap: Keymap,
+    theme: Theme,
+    /// How to display images, if the album art is enabled
+    picker: Option<Picker>,
 }
 
 impl App {
-    pub fn new(args: ArgMatches) -> Self {
-        // Set two Vectors: One for provided IPs, one for provided device names
-        let mut provided_ips: Vec<Ipv4Addr> = Vec::new();
-        let mut provided_names: Vec<String> = Vec::new();
-
-        // Iterate over the provided device argument, if present
-        if let Some(provided_device) = args.get_one::<String>("device") {
-            // Split the device argument by commas and iterate over the single provided devices
-            for e in provided_device.split(',') {
-                // Try to parse the element into an Ipv4Addr, if not possible accept it as a name
-                if let Ok(ip) = Ipv4Addr::from_str(e) {
-                    provided_ips.push(ip);
-                } else {
-                    provided_names.push(e.to_string());
-                }
-            }
-        }
+    pub fn new(config: Config, keymap: Keymap, theme: Theme, picker: Option<Picker>) -> Self {
         App {
-            provided_ips,
-            provided_names,
+            config,
+            keymap,
+            theme,
+            picker,
         }
     }
 
     pub async fn run(self, terminal: &mut DefaultTerminal) -> Result<()> {
-        let mut state = State::Connecting;
-
         // Channel used to send SpeakerState updates from SonosService to the UI
         let (update_tx, mut update_rx) = mpsc::channel(2);
-        // Channel to send commands from the UI to SonosService
-        let (cmd_tx, cmd_rx) = mpsc::channel(2);
+        // Channel to send commands from the UI to SonosService. It's unbounded so that the UI never
+        // waits for the speakers: the commands that pile up get merged by the service.
+        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
 
         // Background service handling all the Sonos stuff
-        let sonos = sonos::SonosService::new(update_tx, cmd_rx);
-        sonos.start((self.provided_ips, self.provided_names));
+        let sonos = sonos::SonosService::new(update_tx, cmd_rx, &self.config);
+
+        // Optional MQTT bridge publishing the state of all the groups
+        if let Some(broker) = &self.config.mqtt {
+            mqtt::MqttBridge::new(
+                broker,
+                sonos.subscribe_groups(),
+                cmd_tx.clone(),
+                self.config.volume_step,
+            )?
+            .start();
+        }
+        // Optional Prometheus metrics endpoint
+        if let Some(addr) = self.config.metrics {
+            metrics::MetricsServer::new(addr, sonos.metrics(), sonos.subscribe_groups()).start();
+        }
+
+        sonos.start(self.config.provided_devices());
+
+        // Album art of the current track, downloaded in the background
+        let (art_tx, mut art_rx) = mpsc::channel(4);
+        let art = self.picker.map(|picker| AlbumArt::new(picker, art_tx));
 
         let mut events = EventStream::new();
+        // Animates the spinner while connecting and the progress bar while playing, and expires
+        // notifications
+        let mut ticker = tokio::time::interval(Duration::from_millis(100));
+        let mut ui = Ui::new(&self.config, &self.keymap, &self.theme, cmd_tx, art);
 
         debug!("Starting main loop...");
         loop {
             select! {
                 event = events.try_next() => {
                     let event = event?.ok_or_else(|| anyhow!("Failed to receive keyboard input"))?;
-                    if let Event::Key(key) = event {
-                        if input::should_quit(&event) {
-                            break;
-                        }
-                        if let State::Ready(ref speaker_state) = state {
-                            let cmd = view::handle_input(&key, speaker_state);
-                            cmd_tx.send(cmd).await?;
-                        }
+                    if ui.handle_event(&event)?.is_break() {
+                        break;
                     }
                 }
-                update = update_rx.recv() => match update {
-                    Some(Update::NewState(speaker_state)) => state = State::Ready(speaker_state),
-                    Some(_) => {},
-                    None => {
-                        // channel was closed for some reason...
-                        warn!("Update channel was closed: exiting main loop");
+                update = update_rx.recv() => {
+                    if ui.handle_update(update).is_break() {
                         break;
                     }
                 }
+                Some(loaded) = art_rx.recv() => {
+                    if let Some(art) = &mut ui.art {
+                        art.loaded(loaded);
+                    }
+                }
+                _ = ticker.tick(), if ui.is_animated() => ui.tick(),
+            }
+            ui.draw(terminal)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// What the main loop keeps track of between two events, apart from the speakers themselves.
+struct Ui<'a> {
+    config: &'a Config,
+    keymap: &'a Keymap,
+    theme: &'a Theme,
+    cmd_tx: UnboundedSender<Request>,
+    state: State,
+    /// Album art of the current track, downloaded in the background
+    art: Option<AlbumArt>,
+    spinner_tick: usize,
+    /// Notifications currently on screen, with when they should disappear
+    notifications: Vec<(Notification, Instant)>,
+    show_help: bool,
+    /// How far down the help popup is scrolled, when it doesn't fit on screen
+    help_scroll: u16,
+    filter: Filter,
+    /// Alarm being created or edited
+    alarm_editor: Option<AlarmEditor>,
+    /// Popup menu, e.g. to pick an input
+    menu: Option<Menu>,
+    /// Popup asking for some text, e.g. a URL to play
+    prompt: Option<Prompt>,
+    /// When the position of the current track was last fetched from the speakers, to advance the
+    /// progress bar between two refreshes
+    position_fetched: Instant,
+    /// Size of the last frame, to find out what the mouse is pointing at
+    area: Rect,
+    /// Changes shown before the speakers confirm them
+    predictions: Predictions,
+}
+
+impl<'a> Ui<'a> {
+    fn new(
+        config: &'a Config,
+        keymap: &'a Keymap,
+        theme: &'a Theme,
+        cmd_tx: UnboundedSender<Request>,
+        art: Option<AlbumArt>,
+    ) -> Self {
+        Self {
+            config,
+            keymap,
+            theme,
+            cmd_tx,
+            state: State::Connecting(vec![]),
+            art,
+            spinner_tick: 0,
+            notifications: vec![],
+            show_help: false,
+            help_scroll: 0,
+            filter: Filter::new(config.view),
+            alarm_editor: None,
+            menu: None,
+            prompt: None,
+            position_fetched: Instant::now(),
+            area: Rect::default(),
+            predictions: Predictions::default(),
+        }
+    }
+
+    /// Show the predicted outcome of a command right away, then send it.
+    fn submit(&mut self, cmd: Action) -> Result<()> {
+        let seq = self.predictions.next_seq();
+        if let State::Ready(speaker_state) = &mut self.state {
+            self.predictions.record(&cmd, seq, speaker_state);
+        }
+        self.cmd_tx.send(Request {
+            action: cmd,
+            seq: Some(seq),
+        })?;
+        Ok(())
+    }
+
+    fn notify(&mut self, notification: Notification) {
+        let expiry = Instant::now() + notification.lifetime();
+        self.notifications.push((notification, expiry));
+    }
+
+    /// Whether the screen changes by itself, and needs redrawing every now and then.
+    fn is_animated(&self) -> bool {
+        let moving = match &self.state {
+            State::Connecting(_) => true,
+            State::Ready(speaker_state) => speaker_state.is_playing,
+            State::Failed(_) => false,
+        };
+        moving || !self.notifications.is_empty()
+    }
+
+    fn tick(&mut self) {
+        self.spinner_tick += 1;
+        let now = Instant::now();
+        self.notifications.retain(|(_, expiry)| *expiry > now);
+    }
+
+    fn handle_event(&mut self, event: &Event) -> Result<ControlFlow<()>> {
+        match event {
+            Event::Mouse(mouse) => self.handle_mouse(mouse)?,
+            Event::Key(key) => return self.handle_key(event, key),
+            _ => {}
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn handle_mouse(&mut self, mouse: &MouseEvent) -> Result<()> {
+        if self.show_help {
+            match mouse.kind {
+                MouseEventKind::ScrollUp => self.help_scroll = self.help_scroll.saturating_sub(1),
+                MouseEventKind::ScrollDown => self.help_scroll = self.help_scroll.saturating_add(1),
+                _ => {}
+            }
+            return Ok(());
+        }
+        let State::Ready(speaker_state) = &self.state else {
+            return Ok(());
+        };
+        if self.alarm_editor.is_some() || self.menu.is_some() || self.prompt.is_some() {
+            return Ok(());
+        }
+
+        let cmd = view::handle_mouse(
+            mouse,
+            speaker_state,
+            &self.filter,
+            self.area,
+            self.config.volume_step,
+        );
+        match cmd {
+            // Moving playback stops the group, so make sure it wasn't a misclick
+            Action::TransferPlayback { to, .. } => {
+                let name = speaker_state.group_names.get(to).cloned();
+                let title = format!("Move playback to {}?", name.unwrap_or_default());
+                let items = vec![("Cancel".to_owned(), Action::Nop), ("Move".to_owned(), cmd)];
+                self.menu = Some(Menu::new(title, items));
+            }
+            // Mouse moves are reported too, don't flood the service with them
+            Action::Nop => {}
+            cmd => self.submit(cmd)?,
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, event: &Event, key: &KeyEvent) -> Result<ControlFlow<()>> {
+        // Popups take all the keys until they're closed
+        if let Some(editor) = &mut self.alarm_editor {
+            match editor.handle_key(key, self.keymap) {
+                EditorResult::Save(alarm) => {
+                    self.alarm_editor = None;
+                    self.submit(Action::SaveAlarm(alarm))?;
+                }
+                EditorResult::Cancel => self.alarm_editor = None,
+                EditorResult::Continue => {}
+            }
+        } else if let Some(menu) = &mut self.menu {
+            let result = menu.handle_key(key);
+            if self.handle_menu_result(result)? {
+                self.menu = None;
+            }
+        } else if let Some(prompt) = &mut self.prompt {
+            let result = prompt.handle_key(key);
+            if self.handle_menu_result(result)? {
+                self.prompt = None;
+            }
+        // While typing a filter, the keys go to the query (apart from the arrows)
+        } else if self.filter.editing
+            && let State::Ready(speaker_state) = &self.state
+        {
+            let cmd = if matches!(key.code, KeyCode::Up | KeyCode::Down) {
+                Some(self.view_command(key, speaker_state))
+            } else if self.filter.handle_key(key) && self.filter.is_active() {
+                Some(view::select_first_match(speaker_state, &self.filter))
+            } else {
+                None
+            };
+            if let Some(cmd) = cmd {
+                self.submit(cmd)?;
+            }
+        } else if input::should_quit(event, self.keymap) {
+            return Ok(ControlFlow::Break(()));
+        } else if self.show_help {
+            self.handle_help_key(key);
+        } else {
+            self.handle_view_key(key)?;
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Act on the outcome of a key pressed in a menu or a prompt. Returns whether it should be
+    /// closed.
+    fn handle_menu_result(&mut self, result: MenuResult) -> Result<bool> {
+        match result {
+            MenuResult::Select(action) => {
+                self.submit(action)?;
+                Ok(true)
+            }
+            MenuResult::Cancel => Ok(true),
+            MenuResult::Continue => Ok(false),
+        }
+    }
+
+    /// The help popup swallows all the keys until it's closed.
+    fn handle_help_key(&mut self, key: &KeyEvent) {
+        match self.keymap.action_for(self.context(), key) {
+            Some(KeyAction::Help) => self.show_help = false,
+            Some(KeyAction::Up) => self.help_scroll = self.help_scroll.saturating_sub(1),
+            Some(KeyAction::Down) => self.help_scroll = self.help_scroll.saturating_add(1),
+            _ if key.code == KeyCode::Esc => self.show_help = false,
+            _ => {}
+        }
+    }
+
+    /// Keys pressed in the main screen: the ones opening a popup are handled here, the others
+    /// are turned into commands by the view.
+    fn handle_view_key(&mut self, key: &KeyEvent) -> Result<()> {
+        let action = self.keymap.action_for(self.context(), key);
+        if action == Some(KeyAction::Help) {
+            self.show_help = true;
+            self.help_scroll = 0;
+            return Ok(());
+        }
+        if self.filter.is_active() && key.code == KeyCode::Esc {
+            self.filter.clear();
+            return Ok(());
+        }
+        let State::Ready(speaker_state) = &self.state else {
+            if matches!(self.state, State::Failed(_)) && action == Some(KeyAction::Retry) {
+                self.submit(Action::Retry)?;
+                self.state = State::Connecting(vec![]);
+            }
+            return Ok(());
+        };
+
+        match action {
+            Some(KeyAction::Filter) => self.filter.start(speaker_state.current_view),
+            Some(action @ (KeyAction::New | KeyAction::Edit)) => {
+                let alarm = if action == KeyAction::New {
+                    // Default to the coordinator of the selected group
+                    speaker_state
+                        .rooms
+                        .iter()
+                        .find(|r| r.group == speaker_state.selected_group)
+                        .map(|r| Alarm::new(r.uuid.clone()))
+                } else if speaker_state.current_view == ViewMode::Alarms {
+                    // Not an alarm hidden by the filter
+                    view::visible_selection(speaker_state, &self.filter)
+                        .and_then(|i| speaker_state.alarms.get(i))
+                        .cloned()
+                } else {
+                    None
+                };
+                self.alarm_editor = alarm.map(|alarm| AlarmEditor::new(alarm, speaker_state));
+            }
+            Some(KeyAction::Delete) if speaker_state.current_view == ViewMode::Alarms => {
+                if let Some(alarm) = view::visible_selection(speaker_state, &self.filter)
+                    .and_then(|i| speaker_state.alarms.get(i))
+                    && let Some(id) = &alarm.id
+                {
+                    let (hours, minutes) = alarm.time();
+                    let title = format!("Delete the alarm at {hours:02}:{minutes:02}?");
+                    let items = vec![
+                        ("Cancel".to_owned(), Action::Nop),
+                        ("Delete".to_owned(), Action::DeleteAlarm(id.clone())),
+                    ];
+                    self.menu = Some(Menu::new(title, items));
+                }
+            }
+            Some(KeyAction::Inputs) => match inputs_menu(speaker_state) {
+                Ok(menu) => self.menu = Some(menu),
+                Err(notification) => self.notify(notification),
+            },
+            Some(action @ (KeyAction::MovePlayback | KeyAction::CopyPlayback)) => {
+                match transfer_menu(speaker_state, action == KeyAction::MovePlayback) {
+                    Ok(menu) => self.menu = Some(menu),
+                    Err(notification) => self.notify(notification),
+                }
+            }
+            Some(KeyAction::Scenes) => match scenes_menu(speaker_state) {
+                Ok(menu) => self.menu = Some(menu),
+                Err(notification) => self.notify(notification),
+            },
+            Some(KeyAction::PlayUrl) => {
+                self.prompt = Some(Prompt::new(
+                    format!("URL to play on {}", speaker_state.group_name()),
+                    |url| Action::PlayUrl {
+                        url,
+                        enqueue: false,
+                    },
+                ));
+            }
+            Some(KeyAction::EnqueueUrl) => {
+                self.prompt = Some(Prompt::new("URL to add to the queue", |url| {
+                    Action::PlayUrl { url, enqueue: true }
+                }));
+            }
+            _ => {
+                let cmd = self.view_command(key, speaker_state);
+                self.submit(cmd)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn view_command(&self, key: &KeyEvent, speaker_state: &SpeakerState) -> Action {
+        view::handle_input(
+            key,
+            speaker_state,
+            &self.filter,
+            self.keymap,
+            self.area,
+            self.config.volume_step,
+        )
+    }
+
+    /// Where the keys are looked up: the current view, or the global bindings until connected.
+    fn context(&self) -> KeyContext {
+        match &self.state {
+            State::Ready(speaker_state) => speaker_state.current_view.into(),
+            _ => KeyContext::Global,
+        }
+    }
+
+    fn handle_update(&mut self, update: Option<Update>) -> ControlFlow<()> {
+        match update {
+            Some(Update::NewState(mut speaker_state)) => {
+                // The filter only applies to the view it was started in
+                if speaker_state.current_view != self.filter.view {
+                    self.filter = Filter::new(speaker_state.current_view);
+                }
+                if speaker_state.current_view != ViewMode::Alarms {
+                    self.alarm_editor = None;
+                }
+                // Each refresh fetches a new TrackInfo, other updates reuse the last one
+                let refreshed = match (&self.state, &speaker_state.now_playing) {
+                    (State::Ready(old), Some(track)) => old
+                        .now_playing
+                        .as_ref()
+                        .is_none_or(|old| !Arc::ptr_eq(old, track)),
+                    _ => true,
+                };
+                if refreshed {
+                    self.position_fetched = Instant::now();
+                }
+                if let Some(art) = &mut self.art {
+                    art.show(speaker_state.album_art.as_deref());
+                }
+                for notification in self.predictions.settle(&mut speaker_state) {
+                    self.notify(notification);
+                }
+                self.state = State::Ready(speaker_state);
+            }
+            Some(Update::Discovered(name)) => {
+                if let State::Connecting(discovered) = &mut self.state {
+                    discovered.push(name);
+                }
             }
-            if let State::Ready(ref speaker_state) = state {
-                terminal.draw(|f| view::render_ui(f, speaker_state))?;
+            Some(Update::Failed(err)) => self.state = State::Failed(err),
+            Some(Update::Notification(notification)) => self.notify(notification),
+            Some(Update::Nop) => {}
+            None => {
+                // channel was closed for some reason...
+                warn!("Update channel was closed: exiting main loop");
+                return ControlFlow::Break(());
             }
         }
+        ControlFlow::Continue(())
+    }
 
+    fn draw(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        let context = self.context();
+        terminal.draw(|f| {
+            self.area = f.area();
+            match &self.state {
+                State::Ready(speaker_state) => {
+                    view::render_ui(
+                        f,
+                        speaker_state,
+                        self.position_fetched.elapsed(),
+                        &self.filter,
+                        self.art.as_mut().and_then(AlbumArt::current),
+                        self.keymap,
+                        self.theme,
+                    );
+                    if let Some(editor) = &self.alarm_editor {
+                        view::render_alarm_editor(f, editor, self.keymap, self.theme);
+                    }
+                    if let Some(menu) = &self.menu {
+                        view::render_menu(f, menu, self.theme);
+                    }
+                    if let Some(prompt) = &self.prompt {
+                        view::render_prompt(f, prompt, self.theme);
+                    }
+                    view::render_notifications(
+                        f,
+                        self.notifications.iter().map(|(n, _)| n),
+                        self.theme,
+                    );
+                }
+                State::Connecting(discovered) => {
+                    view::render_connecting(f, discovered, self.spinner_tick, self.theme)
+                }
+                State::Failed(err) => view::render_failed(f, err, self.keymap, self.theme),
+            }
+            if self.show_help {
+                self.help_scroll =
+                    view::render_help(f, self.keymap, self.theme, context, self.help_scroll);
+            }
+        })?;
         Ok(())
     }
 }
+
+/// Menu of the inputs to play on the selected group.
+fn inputs_menu(speaker_state: &SpeakerState) -> Result<Menu, Notification> {
+    if speaker_state.inputs.is_empty() {
+        return Err(Notification::info("None of the speakers has an input"));
+    }
+    let items = speaker_state
+        .inputs
+        .iter()
+        .enumerate()
+        .map(|(i, input)| (input.name.clone(), Action::PlayInput(i)))
+        .collect();
+    let title = format!("Play on {}", speaker_state.group_name());
+    Ok(Menu::new(title, items))
+}
+
+/// Menu of the groups to move (or copy) the playback of the selected group to.
+fn transfer_menu(speaker_state: &SpeakerState, stop_source: bool) -> Result<Menu, Notification> {
+    let items: Vec<_> = speaker_state
+        .group_names
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != speaker_state.selected_group)
+        .map(|(to, name)| (name.clone(), Action::TransferPlayback { to, stop_source }))
+        .collect();
+    if items.is_empty() {
+        return Err(Notification::info("There is no other group"));
+    }
+    let title = if stop_source {
+        "Move playback to"
+    } else {
+        "Also play on"
+    };
+    Ok(Menu::new(title, items))
+}
+
+fn scenes_menu(speaker_state: &SpeakerState) -> Result<Menu, Notification> {
+    if speaker_state.scenes.is_empty() {
+        return Err(Notification::info(
+            "No scenes are defined in the configuration file",
+        ));
+    }
+    let items = speaker_state
+        .scenes
+        .iter()
+        .map(|name| (name.clone(), Action::ApplyScene(name.clone())))
+        .collect();
+    Ok(Menu::new("Apply scene", items))
+}
@@ -0,0 +1,120 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use anyhow::{Context, Result};
+use image::DynamicImage;
+use ratatui_image::{picker::Picker, protocol::StatefulProtocol};
+use tokio::sync::mpsc::Sender;
+use tracing::{debug, warn};
+
+/// How many downloaded images to keep around, e.g. when switching back and forth between groups
+const CACHE_SIZE: usize = 16;
+
+/// An image that finished downloading (or failed to), by URL.
+pub type LoadedArt = (String, Option<DynamicImage>);
+
+/// Downloads, caches and prepares for display the album art of the current track.
+pub struct AlbumArt {
+    picker: Picker,
+    client: reqwest::Client,
+    loaded_tx: Sender<LoadedArt>,
+    /// Downloaded images, or `None` if they couldn't be downloaded
+    cache: HashMap<String, Option<DynamicImage>>,
+    cache_order: VecDeque<String>,
+    pending: HashSet<String>,
+    /// The image to display, and its URL
+    current: Option<(String, StatefulProtocol)>,
+    wanted: Option<String>,
+}
+
+impl AlbumArt {
+    pub fn new(picker: Picker, loaded_tx: Sender<LoadedArt>) -> Self {
+        Self {
+            picker,
+            client: reqwest::Client::new(),
+            loaded_tx,
+            cache: HashMap::new(),
+            cache_order: VecDeque::new(),
+            pending: HashSet::new(),
+            current: None,
+            wanted: None,
+        }
+    }
+
+    /// Display the art at the given URL, downloading it in the background if needed.
+    pub fn show(&mut self, url: Option<&str>) {
+        if self.wanted.as_deref() == url {
+            return;
+        }
+        self.wanted = url.map(ToOwned::to_owned);
+        self.current = None;
+
+        let Some(url) = url else {
+            return;
+        };
+        match self.cache.get(url) {
+            Some(Some(image)) => {
+                let protocol = self.picker.new_resize_protocol(image.clone());
+                self.current = Some((url.to_owned(), protocol));
+            }
+            // Don't try again to download an image that failed
+            Some(None) => {}
+            None => self.download(url.to_owned()),
+        }
+    }
+
+    /// Store an image that finished downloading, and display it if it's still the one we want.
+    pub fn loaded(&mut self, (url, image): LoadedArt) {
+        self.pending.remove(&url);
+        if self.cache_order.len() >= CACHE_SIZE
+            && let Some(oldest) = self.cache_order.pop_front()
+        {
+            self.cache.remove(&oldest);
+        }
+        self.cache_order.push_back(url.clone());
+        self.cache.insert(url.clone(), image.clone());
+
+        if self.wanted.as_ref() == Some(&url)
+            && let Some(image) = image
+        {
+            let protocol = self.picker.new_resize_protocol(image);
+            self.current = Some((url, protocol));
+        }
+    }
+
+    /// The image to render, if it's been downloaded.
+    pub fn current(&mut self) -> Option<&mut StatefulProtocol> {
+        self.current.as_mut().map(|(_, protocol)| protocol)
+    }
+
+    fn download(&mut self, url: String) {
+        if !self.pending.insert(url.clone()) {
+            return;
+        }
+        let client = self.client.clone();
+        let loaded_tx = self.loaded_tx.clone();
+        tokio::spawn(async move {
+            debug!(%url, "Downloading album art");
+            let image = match fetch_image(&client, &url).await {
+                Ok(image) => Some(image),
+                Err(err) => {
+                    warn!(%url, "Failed to download album art: {err:#}");
+                    None
+                }
+            };
+            let _ = loaded_tx.send((url, image)).await;
+        });
+    }
+}
+
+async fn fetch_image(client: &reqwest::Client, url: &str) -> Result<DynamicImage> {
+    let bytes = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    tokio::task::spawn_blocking(move || image::load_from_memory(&bytes))
+        .await?
+        .context("Failed to decode image")
+}
@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+use clap::ArgMatches;
+use tokio::sync::mpsc;
+use tracing::{debug, info};
+
+use crate::{Action, NotificationLevel, Update, config::Config, sonos::SonosService};
+
+/// The action a subcommand stands for.
+pub fn subcommand_action(name: &str, args: &ArgMatches) -> Result<Action> {
+    match name {
+        "play-url" => Ok(Action::PlayUrl {
+            url: args
+                .get_one::<String>("url")
+                .expect("url is required")
+                .clone(),
+            enqueue: args.get_flag("enqueue"),
+        }),
+        "announce" => Ok(Action::Announce {
+            url: args
+                .get_one::<String>("url")
+                .expect("url is required")
+                .clone(),
+            volume: args.get_one::<u16>("volume").copied(),
+        }),
+        "scene" => match args.subcommand() {
+            Some(("apply", args)) => Ok(Action::ApplyScene(
+                args.get_one::<String>("name")
+                    .expect("name is required")
+                    .clone(),
+            )),
+            Some(("save", args)) => Ok(Action::SaveScene(
+                args.get_one::<String>("name")
+                    .expect("name is required")
+                    .clone(),
+            )),
+            _ => bail!("Unknown scene command"),
+        },
+        "play-file" | "enqueue-file" => Ok(Action::PlayFiles {
+            paths: args
+                .get_many::<PathBuf>("path")
+                .expect("path is required")
+                .cloned()
+                .collect(),
+            enqueue: name == "enqueue-file",
+        }),
+        _ => bail!("Unknown command '{name}'"),
+    }
+}
+
+/// Run a single action against the selected group without the UI, and wait for its outcome.
+///
+/// Local files are only available to the speakers as long as we serve them, so in that case this
+/// keeps running until interrupted.
+pub async fn run(config: &Config, action: Action) -> Result<()> {
+    let serves_files = matches!(action, Action::PlayFiles { .. });
+    let (update_tx, mut update_rx) = mpsc::channel(2);
+    let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+    SonosService::new(update_tx, cmd_rx, config).start(config.provided_devices());
+
+    let mut action = Some(action);
+    while let Some(update) = update_rx.recv().await {
+        match update {
+            // The first state means the speakers are ready
+            Update::NewState(_) => {
+                if let Some(action) = action.take() {
+                    debug!(?action, "Sending command");
+                    cmd_tx.send(action.into())?;
+                }
+            }
+            Update::Discovered(name) => info!("Found {name}"),
+            Update::Failed(err) => bail!("{err}"),
+            // Warnings about the setup, e.g. the configuration
+            Update::Notification(notification) if action.is_some() => {
+                eprintln!("Warning: {}", notification.message);
+            }
+            // Every command reports how it went
+            Update::Notification(notification) => match notification.level {
+                NotificationLevel::Info => {
+                    println!("{}", notification.message);
+                    if serves_files {
+                        println!("Serving the files until interrupted (Ctrl-C)");
+                        return serve_files(update_rx).await;
+                    }
+                    return Ok(());
+                }
+                NotificationLevel::Error => bail!("{}", notification.message),
+            },
+            Update::Nop => {}
+        }
+    }
+    bail!("The connection to the speakers was lost")
+}
+
+/// Keep the service (and so the file server) running until Ctrl-C.
+async fn serve_files(mut update_rx: mpsc::Receiver<Update>) -> Result<()> {
+    loop {
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => return Ok(result?),
+            // Keep draining the updates so that the service doesn't block
+            update = update_rx.recv() => {
+                if update.is_none() {
+                    bail!("The connection to the speakers was lost");
+                }
+            }
+        }
+    }
+}
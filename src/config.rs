@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use clap::ArgMatches;
+use serde_derive::Deserialize;
+
+use crate::{ViewMode, keymap::KeysConfig, scene::ScenesConfig, theme::ThemeConfig};
+
+/// Settings loaded from `$XDG_CONFIG_HOME/sinuous/config.toml`, overridden by the command line.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Speakers to connect to (comma separated IPv4 addresses or names)
+    pub device: Option<String>,
+    /// Name of the group (or of one of its speakers) to select on startup
+    pub group: Option<String>,
+    /// View to display on startup
+    pub view: ViewMode,
+    /// How much the volume changes on each key press
+    pub volume_step: i16,
+    /// Highest volume some rooms can be set to, by name
+    pub max_volume: BTreeMap<String, u16>,
+    /// How long to fade in when playing and out when pausing, in seconds (0 to disable)
+    pub fade: f64,
+    /// Delay between two refreshes of the state of the speakers, in seconds
+    pub poll_interval: f64,
+    /// How long to wait for speakers to answer during discovery, in seconds
+    pub discovery_timeout: f64,
+    /// Where to write the logs
+    pub log_file: PathBuf,
+    /// MQTT broker to publish the state of the groups to
+    pub mqtt: Option<String>,
+    /// Address to expose Prometheus metrics on
+    pub metrics: Option<SocketAddr>,
+    /// Key bindings overriding the default ones, per context
+    pub keys: KeysConfig,
+    /// Colour theme, and styles overriding some of its elements
+    pub theme: ThemeConfig,
+    /// Whether to display the album art of the current track
+    pub album_art: bool,
+    /// Group layouts, volumes and sources that can be recalled by name
+    pub scenes: ScenesConfig,
+    /// Where the configuration file is, even if it doesn't exist yet
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            device: None,
+            group: None,
+            view: ViewMode::Queue,
+            volume_step: 2,
+            max_volume: BTreeMap::new(),
+            fade: 0.0,
+            poll_interval: 1.0,
+            discovery_timeout: 2.0,
+            log_file: std::env::temp_dir().join("sinuous.log"),
+            mqtt: None,
+            metrics: None,
+            keys: KeysConfig::new(),
+            theme: ThemeConfig::default(),
+            album_art: true,
+            scenes: ScenesConfig::new(),
+            path: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration file (if any) and apply the command line arguments on top of it.
+    pub fn from_args(args: &ArgMatches) -> Result<Self> {
+        let path = args.get_one::<PathBuf>("config").cloned();
+        let mut config = match &path {
+            // An explicitly provided config file has to exist
+            Some(path) => Self::load(path)?,
+            None => match default_path() {
+                Some(path) if path.exists() => Self::load(&path)?,
+                _ => Self::default(),
+            },
+        };
+        config.path = path.or_else(default_path);
+
+        if let Some(device) = args.get_one::<String>("device") {
+            config.device = Some(device.clone());
+        }
+        if let Some(group) = args.get_one::<String>("group") {
+            config.group = Some(group.clone());
+        }
+        if let Some(view) = args.get_one::<ViewMode>("view") {
+            config.view = *view;
+        }
+        if let Some(step) = args.get_one::<i16>("volume-step") {
+            config.volume_step = *step;
+        }
+        if let Some(fade) = args.get_one::<f64>("fade") {
+            config.fade = *fade;
+        }
+        if let Some(interval) = args.get_one::<f64>("poll-interval") {
+            config.poll_interval = *interval;
+        }
+        if let Some(timeout) = args.get_one::<f64>("discovery-timeout") {
+            config.discovery_timeout = *timeout;
+        }
+        if let Some(log_file) = args.get_one::<PathBuf>("log-file") {
+            config.log_file = log_file.clone();
+        }
+        if let Some(theme) = args.get_one::<String>("theme") {
+            config.theme.name = Some(theme.clone());
+        }
+        if args.get_flag("no-album-art") {
+            config.album_art = false;
+        }
+        if let Some(broker) = args.get_one::<String>("mqtt") {
+            config.mqtt = Some(broker.clone());
+        }
+        if let Some(addr) = args.get_one::<SocketAddr>("metrics") {
+            config.metrics = Some(*addr);
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))
+    }
+
+    fn validate(&self) -> Result<()> {
+        anyhow::ensure!(
+            (1..=100).contains(&self.volume_step),
+            "volume_step must be between 1 and 100"
+        );
+        anyhow::ensure!(
+            self.poll_interval.is_finite() && self.poll_interval > 0.0,
+            "poll_interval must be a positive number of seconds"
+        );
+        anyhow::ensure!(
+            self.discovery_timeout.is_finite() && self.discovery_timeout > 0.0,
+            "discovery_timeout must be a positive number of seconds"
+        );
+        anyhow::ensure!(
+            self.fade.is_finite() && (0.0..=30.0).contains(&self.fade),
+            "fade must be between 0 and 30 seconds"
+        );
+        anyhow::ensure!(
+            self.max_volume.values().all(|v| *v <= 100),
+            "max_volume must be between 0 and 100"
+        );
+        for (name, scene) in &self.scenes {
+            scene.validate(name)?;
+        }
+        Ok(())
+    }
+
+    /// The speakers given with `device`: the ones given by IP address, and the ones given by name.
+    pub fn provided_devices(&self) -> (Vec<Ipv4Addr>, Vec<String>) {
+        let mut provided_ips: Vec<Ipv4Addr> = Vec::new();
+        let mut provided_names: Vec<String> = Vec::new();
+
+        // Iterate over the provided device argument, if present
+        if let Some(provided_device) = &self.device {
+            // Split the device argument by commas and iterate over the single provided devices
+            for e in provided_device.split(',') {
+                // Try to parse the element into an Ipv4Addr, if not possible accept it as a name
+                if let Ok(ip) = Ipv4Addr::from_str(e) {
+                    provided_ips.push(ip);
+                } else {
+                    provided_names.push(e.to_string());
+                }
+            }
+        }
+        (provided_ips, provided_names)
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs_f64(self.poll_interval)
+    }
+
+    pub fn fade(&self) -> Duration {
+        Duration::from_secs_f64(self.fade)
+    }
+
+    pub fn discovery_timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.discovery_timeout)
+    }
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("sinuous").join("config.toml"))
+}
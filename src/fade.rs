@@ -0,0 +1,119 @@
+use std::{sync::Arc, time::Duration};
+
+use sonor::Speaker;
+use tokio::{select, sync::oneshot, task::JoinHandle};
+use tracing::{debug, warn};
+
+use crate::metrics::Metrics;
+
+/// Number of volume changes in a ramp
+const STEPS: u32 = 10;
+
+/// A speaker of the group being faded, and the volume it's at when not faded.
+pub struct Level {
+    pub speaker: Speaker,
+    pub volume: u16,
+}
+
+/// A volume ramp running in the background.
+///
+/// Cancelling it (or letting it finish) always leaves the speakers at their normal volume, so that
+/// e.g. pausing and then playing again doesn't start silently.
+pub struct Fade {
+    cancel: oneshot::Sender<()>,
+    handle: JoinHandle<()>,
+    /// Whether it ends with a pause
+    out: bool,
+}
+
+impl Fade {
+    /// Bring the speakers up from 0 to their normal volume. They should already be playing, at 0.
+    pub fn fade_in(metrics: Arc<Metrics>, levels: Vec<Level>, duration: Duration) -> Self {
+        Self::spawn(false, async move |mut cancel| {
+            if !ramp(&metrics, &levels, 0.0, 1.0, duration, &mut cancel).await {
+                debug!("Fade in cancelled");
+                set_levels(&metrics, &levels, 1.0).await;
+            }
+        })
+    }
+
+    /// Bring the speakers down to 0, pause `coordinator`, then put the volumes back.
+    pub fn fade_out(
+        metrics: Arc<Metrics>,
+        coordinator: Speaker,
+        levels: Vec<Level>,
+        duration: Duration,
+    ) -> Self {
+        Self::spawn(true, async move |mut cancel| {
+            if ramp(&metrics, &levels, 1.0, 0.0, duration, &mut cancel).await {
+                if let Err(err) = metrics.track("Pause", coordinator.pause()).await {
+                    warn!(%err, "Failed to pause at the end of the fade");
+                }
+            } else {
+                debug!("Fade out cancelled");
+            }
+            set_levels(&metrics, &levels, 1.0).await;
+        })
+    }
+
+    fn spawn<F>(out: bool, ramp: impl FnOnce(oneshot::Receiver<()>) -> F) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let (cancel, cancelled) = oneshot::channel();
+        let handle = tokio::spawn(ramp(cancelled));
+        Self {
+            cancel,
+            handle,
+            out,
+        }
+    }
+
+    /// Whether this is fading out to pause, and hasn't paused yet.
+    pub fn is_pausing(&self) -> bool {
+        self.out && !self.handle.is_finished()
+    }
+
+    /// Stop the ramp, and wait for the volumes to be back to normal.
+    pub async fn cancel(self) {
+        // The fade may already be over
+        let _ = self.cancel.send(());
+        if let Err(err) = self.handle.await {
+            warn!(%err, "Fade task failed");
+        }
+    }
+}
+
+/// Move the volumes from `from` to `to` (as ratios of the normal volumes) over `duration`.
+/// Returns false if it was cancelled before reaching `to`.
+async fn ramp(
+    metrics: &Metrics,
+    levels: &[Level],
+    from: f64,
+    to: f64,
+    duration: Duration,
+    cancel: &mut oneshot::Receiver<()>,
+) -> bool {
+    for step in 1..=STEPS {
+        select! {
+            _ = tokio::time::sleep(duration / STEPS) => {}
+            // Also fires if the service went away
+            _ = &mut *cancel => return false,
+        }
+        let ratio = from + (to - from) * f64::from(step) / f64::from(STEPS);
+        set_levels(metrics, levels, ratio).await;
+    }
+    true
+}
+
+async fn set_levels(metrics: &Metrics, levels: &[Level], ratio: f64) {
+    for level in levels {
+        let volume = (f64::from(level.volume) * ratio).round() as u16;
+        if let Err(err) = metrics
+            .track("SetVolume", level.speaker.set_volume(volume))
+            .await
+        {
+            warn!(%err, "Failed to change the volume while fading");
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use std::{
+    collections::HashSet,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{debug, error, info, warn};
+
+use crate::http;
+
+/// Extensions of the files the speakers can play, with their MIME type
+const AUDIO_TYPES: &[(&str, &str)] = &[
+    ("mp3", "audio/mpeg"),
+    ("flac", "audio/flac"),
+    ("m4a", "audio/mp4"),
+    ("mp4", "audio/mp4"),
+    ("aac", "audio/aac"),
+    ("ogg", "audio/ogg"),
+    ("oga", "audio/ogg"),
+    ("opus", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("aif", "audio/aiff"),
+    ("aiff", "audio/aiff"),
+];
+
+/// Minimal HTTP server making local files available to the speakers, which fetch what they play
+/// themselves.
+///
+/// Only the files that were explicitly shared are served, under an opaque path.
+pub struct FileServer {
+    base_url: String,
+    files: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+impl FileServer {
+    /// Start serving on the interface the speaker at `speaker_ip` can reach us through.
+    pub async fn start(speaker_ip: IpAddr) -> Result<Self> {
+        let ip = local_ip_for(speaker_ip)
+            .with_context(|| format!("Failed to find a route to {speaker_ip}"))?;
+        let listener = TcpListener::bind(SocketAddr::new(ip, 0))
+            .await
+            .with_context(|| format!("Failed to bind the file server to {ip}"))?;
+        let addr = listener.local_addr()?;
+        info!("Serving files on http://{addr}");
+
+        let files = Arc::new(Mutex::new(vec![]));
+        let served = files.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(connection) => connection,
+                    Err(err) => {
+                        error!(%err, "File server error");
+                        break;
+                    }
+                };
+                debug!(%peer, "File request");
+                let files = served.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, files).await {
+                        warn!("Failed to serve file: {}", e);
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            base_url: format!("http://{addr}"),
+            files,
+        })
+    }
+
+    /// Share a file, and get the URL it can be fetched from.
+    pub fn url(&self, path: &Path) -> String {
+        let mut files = self.files.lock().expect("file server lock poisoned");
+        let index = files.iter().position(|f| f == path).unwrap_or_else(|| {
+            files.push(path.to_owned());
+            files.len() - 1
+        });
+        // Keep the extension, which some speakers look at to find out the format
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        format!("{}/files/{index}.{extension}", self.base_url)
+    }
+}
+
+/// The audio files among the given paths, looking into directories recursively.
+pub fn audio_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut visited = HashSet::new();
+    for path in paths {
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        if path.is_dir() {
+            add_directory(&path, &mut files, &mut visited)?;
+        } else if content_type(&path).is_some() {
+            files.push(path);
+        } else if paths.len() == 1 {
+            anyhow::bail!("{} is not an audio file", path.display());
+        }
+    }
+    Ok(files)
+}
+
+/// Add the audio files of a directory and its subdirectories, skipping anything else. Symbolic
+/// links may point back up the tree, so each directory is only looked into once.
+fn add_directory(
+    dir: &Path,
+    files: &mut Vec<PathBuf>,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<()> {
+    if !visited.insert(dir.to_owned()) {
+        return Ok(());
+    }
+    let mut entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to list {}", dir.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    // Play albums in order
+    entries.sort();
+    for entry in entries {
+        let path = match entry.canonicalize() {
+            Ok(path) => path,
+            Err(err) => {
+                warn!(%err, "Skipping {}", entry.display());
+                continue;
+            }
+        };
+        if path.is_dir() {
+            add_directory(&path, files, visited)?;
+        } else if content_type(&path).is_some() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn content_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    AUDIO_TYPES
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, mime)| *mime)
+}
+
+/// Address of the interface the packets to `remote` go through. Nothing is actually sent.
+fn local_ip_for(remote: IpAddr) -> Result<IpAddr> {
+    let socket = UdpSocket::bind(SocketAddr::new([0, 0, 0, 0].into(), 0))?;
+    socket.connect(SocketAddr::new(remote, 1400))?;
+    Ok(socket.local_addr()?.ip())
+}
+
+async fn serve(mut stream: TcpStream, files: Arc<Mutex<Vec<PathBuf>>>) -> Result<()> {
+    let request = http::read_request(&mut stream).await?;
+    let method = request.method.as_str();
+    let range = request.header("Range");
+
+    // Files are served as /files/<index>.<extension>
+    let path = request
+        .target
+        .strip_prefix("/files/")
+        .and_then(|name| name.split('.').next()?.parse::<usize>().ok())
+        .and_then(|i| {
+            let files = files.lock().expect("file server lock poisoned");
+            files.get(i).cloned()
+        });
+    let (method @ ("GET" | "HEAD"), Some(path)) = (method, path) else {
+        return respond(&mut stream, "404 Not Found", &[]).await;
+    };
+    let Ok(mut file) = File::open(&path).await else {
+        return respond(&mut stream, "404 Not Found", &[]).await;
+    };
+    let size = file.metadata().await?.len();
+
+    let (status, start, length) = match range {
+        Some(range) => match parse_range(range, size) {
+            Some((start, end)) => ("206 Partial Content", start, end - start + 1),
+            None => {
+                let content_range = format!("bytes */{size}");
+                return respond(
+                    &mut stream,
+                    "416 Range Not Satisfiable",
+                    &[("Content-Range", &content_range)],
+                )
+                .await;
+            }
+        },
+        None => ("200 OK", 0, size),
+    };
+
+    let mut headers = vec![
+        (
+            "Content-Type",
+            content_type(&path)
+                .unwrap_or("application/octet-stream")
+                .to_owned(),
+        ),
+        ("Content-Length", length.to_string()),
+        ("Accept-Ranges", "bytes".to_owned()),
+    ];
+    if status.starts_with("206") {
+        headers.push((
+            "Content-Range",
+            format!("bytes {start}-{}/{size}", start + length - 1),
+        ));
+    }
+    let headers: Vec<_> = headers.iter().map(|(n, v)| (*n, v.as_str())).collect();
+    write_head(&mut stream, status, &headers).await?;
+
+    if method == "GET" {
+        file.seek(std::io::SeekFrom::Start(start)).await?;
+        tokio::io::copy(&mut file.take(length), &mut stream).await?;
+    }
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// First and last byte of a `Range` header such as `bytes=100-199`, `bytes=100-` or `bytes=-100`,
+/// or `None` if it can't be satisfied. Only the first range of the header is honoured.
+fn parse_range(header: &str, size: u64) -> Option<(u64, u64)> {
+    let spec = header.strip_prefix("bytes=")?.split(',').next()?.trim();
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            (size.checked_sub(suffix.min(size))?, size.checked_sub(1)?)
+        }
+        (start, "") => (start.parse().ok()?, size.checked_sub(1)?),
+        (start, end) => (
+            start.parse().ok()?,
+            end.parse::<u64>().ok()?.min(size.checked_sub(1)?),
+        ),
+    };
+    (start <= end && start < size).then_some((start, end))
+}
+
+async fn write_head(stream: &mut TcpStream, status: &str, headers: &[(&str, &str)]) -> Result<()> {
+    let mut head = format!("HTTP/1.1 {status}\r\n");
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("Connection: close\r\n\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    Ok(())
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, headers: &[(&str, &str)]) -> Result<()> {
+    let mut headers = headers.to_vec();
+    headers.push(("Content-Length", "0"));
+    write_head(stream, status, &headers).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_range;
+
+    #[test]
+    fn parse_range_bounded() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some((0, 99)));
+        assert_eq!(parse_range("bytes=900-5000", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=5-2", 1000), None);
+    }
+
+    #[test]
+    fn parse_range_open_ended() {
+        assert_eq!(parse_range("bytes=100-", 1000), Some((100, 999)));
+        assert_eq!(parse_range("bytes=999-", 1000), Some((999, 999)));
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+    }
+
+    #[test]
+    fn parse_range_suffix() {
+        assert_eq!(parse_range("bytes=-100", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=-2000", 1000), Some((0, 999)));
+        assert_eq!(parse_range("bytes=-0", 1000), None);
+    }
+
+    #[test]
+    fn parse_range_unsatisfiable() {
+        assert_eq!(parse_range("bytes=0-99", 0), None);
+        assert_eq!(parse_range("bytes=-100", 0), None);
+        assert_eq!(parse_range("items=0-99", 1000), None);
+        assert_eq!(parse_range("bytes=a-b", 1000), None);
+    }
+
+    #[test]
+    fn parse_range_first_of_several() {
+        assert_eq!(parse_range("bytes=0-1, 5-6", 1000), Some((0, 1)));
+    }
+}
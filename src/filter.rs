@@ -0,0 +1,135 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::ViewMode;
+
+/// Narrows down the items of the list in the current view to the ones matching a query.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    /// View the filter applies to
+    pub view: ViewMode,
+    pub query: String,
+    /// Whether keys are being typed into the query
+    pub editing: bool,
+}
+
+impl Filter {
+    pub fn new(view: ViewMode) -> Self {
+        Self {
+            view,
+            query: String::new(),
+            editing: false,
+        }
+    }
+
+    /// Start typing into the query of the given view, keeping the current one if it's the same.
+    pub fn start(&mut self, view: ViewMode) {
+        if view != self.view {
+            *self = Self::new(view);
+        }
+        self.editing = true;
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new(self.view);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.editing || !self.query.is_empty()
+    }
+
+    /// Edit the query with a key. Returns whether the query changed.
+    pub fn handle_key(&mut self, key: &KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Esc => {
+                self.clear();
+                true
+            }
+            KeyCode::Enter => {
+                self.editing = false;
+                false
+            }
+            KeyCode::Backspace => self.query.pop().is_some(),
+            KeyCode::Char(c)
+                if !key
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                self.query.push(c);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Positions (in chars) of the characters of `text` matching the query, or `None` if it
+    /// doesn't match at all.
+    pub fn matches(&self, text: &str) -> Option<Vec<usize>> {
+        fuzzy_match(&self.query, text)
+    }
+}
+
+/// Case insensitive fuzzy matching: all the characters of the query have to appear in the text,
+/// in the same order. A contiguous match is preferred when there is one.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<Vec<usize>> {
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    if query.is_empty() {
+        return Some(vec![]);
+    }
+    // Some characters are several once lowercased (e.g. 'İ'): they all keep the position of the
+    // character they come from
+    let (positions, text): (Vec<usize>, Vec<char>) = text
+        .chars()
+        .enumerate()
+        .flat_map(|(pos, c)| c.to_lowercase().map(move |c| (pos, c)))
+        .unzip();
+
+    let mut matched = match text.windows(query.len()).position(|w| w == query) {
+        Some(start) => positions[start..start + query.len()].to_vec(),
+        None => {
+            let mut matched = Vec::with_capacity(query.len());
+            let mut chars = text.iter().zip(&positions);
+            for q in &query {
+                let (_, pos) = chars.find(|(c, _)| *c == q)?;
+                matched.push(*pos);
+            }
+            matched
+        }
+    };
+    matched.dedup();
+    Some(matched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fuzzy_match;
+
+    #[test]
+    fn contiguous_match() {
+        assert_eq!(fuzzy_match("beat", "The Beatles"), Some(vec![4, 5, 6, 7]));
+        assert_eq!(fuzzy_match("", "anything"), Some(vec![]));
+    }
+
+    #[test]
+    fn scattered_match() {
+        assert_eq!(fuzzy_match("tbs", "The Beatles"), Some(vec![0, 4, 10]));
+        // The characters have to be in the same order
+        assert_eq!(fuzzy_match("sbt", "The Beatles"), None);
+        assert_eq!(fuzzy_match("beatlesz", "The Beatles"), None);
+    }
+
+    #[test]
+    fn case_insensitive() {
+        assert_eq!(fuzzy_match("ÉTÉ", "un été"), Some(vec![3, 4, 5]));
+        assert_eq!(fuzzy_match("abba", "ABBA"), Some(vec![0, 1, 2, 3]));
+    }
+
+    #[test]
+    fn multiple_char_lowercase() {
+        // 'İ' is 'i' followed by a combining dot once lowercased
+        assert_eq!(fuzzy_match("İ", "İstanbul"), Some(vec![0]));
+        assert_eq!(fuzzy_match("İst", "İstanbul"), Some(vec![0, 1, 2]));
+        assert_eq!(fuzzy_match("is", "İstanbul"), Some(vec![0, 1]));
+        // Positions are still in characters of the text after it
+        assert_eq!(fuzzy_match("bul", "İstanbul"), Some(vec![5, 6, 7]));
+    }
+}
@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use tokio::{io::AsyncReadExt, net::TcpStream};
+
+/// Largest request head the servers accept
+const MAX_HEAD: usize = 8192;
+/// How long a client has to send the head of its request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The head of a request to one of the small HTTP servers (metrics, local files): the request line
+/// and the headers. The body, if any, is ignored.
+#[derive(Debug, Default)]
+pub struct RequestHead {
+    pub method: String,
+    pub target: String,
+    headers: Vec<(String, String)>,
+}
+
+impl RequestHead {
+    fn parse(head: &str) -> Self {
+        let mut lines = head.lines();
+        let mut parts = lines.next().unwrap_or_default().split_whitespace();
+        let (method, target) = (parts.next(), parts.next());
+        let headers = lines
+            .filter_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                Some((name.trim().to_owned(), value.trim().to_owned()))
+            })
+            .collect();
+        Self {
+            method: method.unwrap_or_default().to_owned(),
+            target: target.unwrap_or_default().to_owned(),
+            headers,
+        }
+    }
+
+    /// Value of the first header of that name, whatever its case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Read the head of the request, up to the blank line ending it. Reading the whole of it, even
+/// when only the request line matters, keeps the client from getting a connection reset.
+pub async fn read_request(stream: &mut TcpStream) -> Result<RequestHead> {
+    let mut buf = vec![0; MAX_HEAD];
+    let mut len = 0;
+    let read = async {
+        while len < buf.len() {
+            let n = stream.read(&mut buf[len..]).await?;
+            if n == 0 {
+                break;
+            }
+            len += n;
+            if buf[..len].windows(4).any(|w| w == b"\r\n\r\n") {
+                break;
+            }
+        }
+        Ok::<_, std::io::Error>(())
+    };
+    match tokio::time::timeout(READ_TIMEOUT, read).await {
+        Ok(result) => result.context("Failed to read the request")?,
+        Err(_) => bail!("Timed out reading the request"),
+    }
+    Ok(RequestHead::parse(&String::from_utf8_lossy(&buf[..len])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestHead;
+
+    #[test]
+    fn parse_head() {
+        let head = RequestHead::parse(
+            "GET /files/0.mp3 HTTP/1.1\r\nHost: 10.0.0.2\r\nrange: bytes=0-99\r\n\r\n",
+        );
+        assert_eq!(head.method, "GET");
+        assert_eq!(head.target, "/files/0.mp3");
+        assert_eq!(head.header("Range"), Some("bytes=0-99"));
+        assert_eq!(head.header("host"), Some("10.0.0.2"));
+        assert_eq!(head.header("Accept"), None);
+    }
+
+    #[test]
+    fn parse_garbage() {
+        let head = RequestHead::parse("");
+        assert_eq!((head.method.as_str(), head.target.as_str()), ("", ""));
+    }
+}
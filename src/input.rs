@@ -1,11 +1,10 @@
-use crossterm::event::{Event, KeyCode, KeyEvent};
+use crossterm::event::Event;
 
-pub fn should_quit(key: &Event) -> bool {
-    matches!(
-        key,
-        Event::Key(KeyEvent {
-            code: KeyCode::Char('q'),
-            ..
-        })
-    )
+use crate::keymap::{KeyAction, KeyContext, Keymap};
+
+pub fn should_quit(event: &Event, keymap: &Keymap) -> bool {
+    match event {
+        Event::Key(key) => keymap.action_for(KeyContext::Global, key) == Some(KeyAction::Quit),
+        _ => false,
+    }
 }
@@ -0,0 +1,730 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow, bail};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::ViewMode;
+
+/// Raw key bindings, as found in the configuration file: context -> action -> key chords.
+pub type KeysConfig = BTreeMap<String, BTreeMap<String, Vec<String>>>;
+
+/// Where a key binding applies. Bindings of the current view take precedence over the global
+/// ones, while those of a popup (e.g. the alarm editor) replace them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum KeyContext {
+    Global,
+    Queue,
+    Favorites,
+    Overview,
+    Alarms,
+    AlarmEditor,
+}
+
+impl KeyContext {
+    pub const ALL: &[KeyContext] = &[
+        KeyContext::Global,
+        KeyContext::Queue,
+        KeyContext::Favorites,
+        KeyContext::Overview,
+        KeyContext::Alarms,
+        KeyContext::AlarmEditor,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyContext::Global => "global",
+            KeyContext::Queue => "queue",
+            KeyContext::Favorites => "favorites",
+            KeyContext::Overview => "overview",
+            KeyContext::Alarms => "alarms",
+            KeyContext::AlarmEditor => "alarm_editor",
+        }
+    }
+
+    /// Whether the global bindings are left out, as a popup takes all the keys.
+    fn is_modal(self) -> bool {
+        self == KeyContext::AlarmEditor
+    }
+}
+
+impl From<ViewMode> for KeyContext {
+    fn from(view: ViewMode) -> Self {
+        match view {
+            ViewMode::Queue => KeyContext::Queue,
+            ViewMode::Favorites => KeyContext::Favorites,
+            ViewMode::Overview => KeyContext::Overview,
+            ViewMode::Alarms => KeyContext::Alarms,
+        }
+    }
+}
+
+impl FromStr for KeyContext {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        KeyContext::ALL
+            .iter()
+            .copied()
+            .find(|c| c.name() == s)
+            .ok_or_else(|| {
+                anyhow!(
+                    "unknown key context '{s}' (expected one of: {})",
+                    KeyContext::ALL
+                        .iter()
+                        .map(|c| c.name())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+}
+
+/// Named actions that keys can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum KeyAction {
+    Quit,
+    PlayPause,
+    Next,
+    Previous,
+    VolumeUp,
+    VolumeDown,
+    NextGroup,
+    PreviousGroup,
+    QueueView,
+    FavoritesView,
+    OverviewView,
+    AlarmsView,
+    Up,
+    Down,
+    Left,
+    Right,
+    PlaySelected,
+    Retry,
+    Help,
+    Filter,
+    Inputs,
+    PlayUrl,
+    EnqueueUrl,
+    Scenes,
+    MovePlayback,
+    CopyPlayback,
+    New,
+    Edit,
+    Toggle,
+    Delete,
+    Decrease,
+    Increase,
+    Save,
+    Cancel,
+}
+
+impl KeyAction {
+    pub const ALL: &[KeyAction] = &[
+        KeyAction::Quit,
+        KeyAction::PlayPause,
+        KeyAction::Next,
+        KeyAction::Previous,
+        KeyAction::VolumeUp,
+        KeyAction::VolumeDown,
+        KeyAction::NextGroup,
+        KeyAction::PreviousGroup,
+        KeyAction::QueueView,
+        KeyAction::FavoritesView,
+        KeyAction::OverviewView,
+        KeyAction::AlarmsView,
+        KeyAction::Up,
+        KeyAction::Down,
+        KeyAction::Left,
+        KeyAction::Right,
+        KeyAction::PlaySelected,
+        KeyAction::Retry,
+        KeyAction::Help,
+        KeyAction::Filter,
+        KeyAction::Inputs,
+        KeyAction::PlayUrl,
+        KeyAction::EnqueueUrl,
+        KeyAction::Scenes,
+        KeyAction::MovePlayback,
+        KeyAction::CopyPlayback,
+        KeyAction::New,
+        KeyAction::Edit,
+        KeyAction::Toggle,
+        KeyAction::Delete,
+        KeyAction::Decrease,
+        KeyAction::Increase,
+        KeyAction::Save,
+        KeyAction::Cancel,
+    ];
+
+    /// Name used in the configuration file
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyAction::Quit => "quit",
+            KeyAction::PlayPause => "play_pause",
+            KeyAction::Next => "next",
+            KeyAction::Previous => "previous",
+            KeyAction::VolumeUp => "volume_up",
+            KeyAction::VolumeDown => "volume_down",
+            KeyAction::NextGroup => "next_group",
+            KeyAction::PreviousGroup => "previous_group",
+            KeyAction::QueueView => "queue_view",
+            KeyAction::FavoritesView => "favorites_view",
+            KeyAction::OverviewView => "overview_view",
+            KeyAction::AlarmsView => "alarms_view",
+            KeyAction::Up => "up",
+            KeyAction::Down => "down",
+            KeyAction::Left => "left",
+            KeyAction::Right => "right",
+            KeyAction::PlaySelected => "play_selected",
+            KeyAction::Retry => "retry",
+            KeyAction::Help => "help",
+            KeyAction::Filter => "filter",
+            KeyAction::Inputs => "inputs",
+            KeyAction::PlayUrl => "play_url",
+            KeyAction::EnqueueUrl => "enqueue_url",
+            KeyAction::Scenes => "scenes",
+            KeyAction::MovePlayback => "move_playback",
+            KeyAction::CopyPlayback => "copy_playback",
+            KeyAction::New => "new",
+            KeyAction::Edit => "edit",
+            KeyAction::Toggle => "toggle",
+            KeyAction::Delete => "delete",
+            KeyAction::Decrease => "decrease",
+            KeyAction::Increase => "increase",
+            KeyAction::Save => "save",
+            KeyAction::Cancel => "cancel",
+        }
+    }
+
+    /// What the action does, as shown in the help
+    pub fn description(self) -> &'static str {
+        match self {
+            KeyAction::Quit => "Quit",
+            KeyAction::PlayPause => "Play / pause",
+            KeyAction::Next => "Next track",
+            KeyAction::Previous => "Previous track",
+            KeyAction::VolumeUp => "Volume up",
+            KeyAction::VolumeDown => "Volume down",
+            KeyAction::NextGroup => "Next group",
+            KeyAction::PreviousGroup => "Previous group",
+            KeyAction::QueueView => "Queue view",
+            KeyAction::FavoritesView => "Favorites view",
+            KeyAction::OverviewView => "Overview of all the groups",
+            KeyAction::AlarmsView => "Alarms view",
+            KeyAction::Up => "Move up",
+            KeyAction::Down => "Move down",
+            KeyAction::Left => "Move left",
+            KeyAction::Right => "Move right",
+            KeyAction::PlaySelected => "Play the selection",
+            KeyAction::Retry => "Retry connecting",
+            KeyAction::Help => "Show / hide this help",
+            KeyAction::Filter => "Filter the list (ESC to clear)",
+            KeyAction::Inputs => "Play a line-in or TV input",
+            KeyAction::PlayUrl => "Play a URL",
+            KeyAction::EnqueueUrl => "Add a URL to the queue",
+            KeyAction::Scenes => "Apply a scene",
+            KeyAction::MovePlayback => "Move playback to another group",
+            KeyAction::CopyPlayback => "Play the same thing on another group",
+            KeyAction::New => "Create an alarm",
+            KeyAction::Edit => "Edit the selection",
+            KeyAction::Toggle => "Enable / disable the selection",
+            KeyAction::Delete => "Delete the selection",
+            KeyAction::Decrease => "Previous value of the setting",
+            KeyAction::Increase => "Next value of the setting",
+            KeyAction::Save => "Save",
+            KeyAction::Cancel => "Cancel",
+        }
+    }
+}
+
+impl FromStr for KeyAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        KeyAction::ALL
+            .iter()
+            .copied()
+            .find(|a| a.name() == s)
+            .ok_or_else(|| {
+                anyhow!(
+                    "unknown action '{s}' (expected one of: {})",
+                    KeyAction::ALL
+                        .iter()
+                        .map(|a| a.name())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+}
+
+/// A key with its modifiers, e.g. `ctrl+c` or `shift+tab`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        // Terminals don't agree on how to report shift+tab
+        let code = match code {
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+            code => code,
+        };
+        // Shift is already part of the character (or implied by BackTab)
+        if matches!(code, KeyCode::Char(_) | KeyCode::BackTab) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self { code, modifiers }
+    }
+}
+
+impl From<&KeyEvent> for KeyChord {
+    fn from(key: &KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // The key itself can be '+', e.g. "ctrl++"
+        let (modifiers, key) = match s.strip_suffix('+') {
+            Some(rest) if rest.is_empty() || rest.ends_with('+') => (rest, "+"),
+            _ => s.rsplit_once('+').unwrap_or(("", s)),
+        };
+
+        let mut mods = KeyModifiers::NONE;
+        for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+            mods |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => bail!("unknown modifier '{modifier}' in key '{s}'"),
+            };
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) if mods.contains(KeyModifiers::SHIFT) => {
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_ascii_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "enter" | "return" => KeyCode::Enter,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "esc" | "escape" => KeyCode::Esc,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                name => match name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n) if (1..=12).contains(&n) => KeyCode::F(n),
+                    _ => bail!("unknown key '{key}' in '{s}'"),
+                },
+            },
+        };
+
+        Ok(Self::new(code, mods))
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "CTRL+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "ALT+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "SHIFT+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "SPACE"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::Enter => write!(f, "ENTER"),
+            KeyCode::Tab => write!(f, "TAB"),
+            KeyCode::BackTab => write!(f, "SHIFT+TAB"),
+            KeyCode::Esc => write!(f, "ESC"),
+            KeyCode::Backspace => write!(f, "BACKSPACE"),
+            KeyCode::Delete => write!(f, "DEL"),
+            KeyCode::Insert => write!(f, "INS"),
+            KeyCode::Up => write!(f, "↑"),
+            KeyCode::Down => write!(f, "↓"),
+            KeyCode::Left => write!(f, "←"),
+            KeyCode::Right => write!(f, "→"),
+            KeyCode::Home => write!(f, "HOME"),
+            KeyCode::End => write!(f, "END"),
+            KeyCode::PageUp => write!(f, "PGUP"),
+            KeyCode::PageDown => write!(f, "PGDN"),
+            KeyCode::F(n) => write!(f, "F{n}"),
+            code => write!(f, "{code:?}"),
+        }
+    }
+}
+
+/// The active key bindings: the built-in defaults, with the ones from the configuration file
+/// applied on top.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: BTreeMap<KeyContext, Vec<(KeyAction, Vec<KeyChord>)>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        use KeyAction::*;
+
+        let defaults: &[(KeyContext, KeyAction, &[&str])] = &[
+            (KeyContext::Global, Quit, &["q"]),
+            (KeyContext::Global, PlayPause, &["space"]),
+            (KeyContext::Global, Next, &["n"]),
+            (KeyContext::Global, Previous, &["p"]),
+            (KeyContext::Global, VolumeDown, &["["]),
+            (KeyContext::Global, VolumeUp, &["]"]),
+            (KeyContext::Global, NextGroup, &["tab"]),
+            (KeyContext::Global, PreviousGroup, &["shift+tab"]),
+            (KeyContext::Global, QueueView, &["1"]),
+            (KeyContext::Global, FavoritesView, &["2"]),
+            (KeyContext::Global, OverviewView, &["3"]),
+            (KeyContext::Global, AlarmsView, &["4"]),
+            (KeyContext::Global, Retry, &["r"]),
+            (KeyContext::Global, Help, &["?"]),
+            (KeyContext::Global, Inputs, &["i"]),
+            (KeyContext::Global, PlayUrl, &["u"]),
+            (KeyContext::Global, EnqueueUrl, &["U"]),
+            (KeyContext::Global, Scenes, &["S"]),
+            (KeyContext::Global, MovePlayback, &["m"]),
+            (KeyContext::Global, CopyPlayback, &["M"]),
+            (KeyContext::Queue, Up, &["up", "k"]),
+            (KeyContext::Queue, Down, &["down", "j"]),
+            (KeyContext::Queue, PlaySelected, &["enter"]),
+            (KeyContext::Queue, Filter, &["/"]),
+            (KeyContext::Favorites, Up, &["up", "k"]),
+            (KeyContext::Favorites, Down, &["down", "j"]),
+            (KeyContext::Favorites, PlaySelected, &["enter"]),
+            (KeyContext::Favorites, Filter, &["/"]),
+            (KeyContext::Overview, Up, &["up", "k"]),
+            (KeyContext::Overview, Down, &["down", "j"]),
+            (KeyContext::Overview, Left, &["left", "h"]),
+            (KeyContext::Overview, Right, &["right", "l"]),
+            (KeyContext::Overview, PlaySelected, &["enter"]),
+            (KeyContext::Alarms, Up, &["up", "k"]),
+            (KeyContext::Alarms, Down, &["down", "j"]),
+            (KeyContext::Alarms, Edit, &["enter", "e"]),
+            (KeyContext::Alarms, New, &["a"]),
+            (KeyContext::Alarms, Toggle, &["t"]),
+            (KeyContext::Alarms, Delete, &["d", "delete"]),
+            (KeyContext::Alarms, Filter, &["/"]),
+            (KeyContext::AlarmEditor, Up, &["up", "shift+tab"]),
+            (KeyContext::AlarmEditor, Down, &["down", "tab"]),
+            (KeyContext::AlarmEditor, Decrease, &["left"]),
+            (KeyContext::AlarmEditor, Increase, &["right"]),
+            (KeyContext::AlarmEditor, Toggle, &["space"]),
+            (KeyContext::AlarmEditor, Save, &["enter"]),
+            (KeyContext::AlarmEditor, Cancel, &["esc"]),
+        ];
+
+        let mut keymap = Self {
+            bindings: BTreeMap::new(),
+        };
+        for (context, action, chords) in defaults {
+            let chords = chords
+                .iter()
+                .map(|c| c.parse().expect("invalid default key binding"))
+                .collect();
+            keymap.bind(*context, *action, chords);
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    /// Build the keymap from the `[keys.<context>]` sections of the configuration file, and make
+    /// sure no key is bound to more than one action.
+    pub fn from_config(config: &KeysConfig) -> Result<Self> {
+        let mut keymap = Self::default();
+        for (context, actions) in config {
+            let context: KeyContext = context.parse()?;
+            for (action, chords) in actions {
+                let action: KeyAction = action.parse()?;
+                let chords = chords
+                    .iter()
+                    .map(|c| c.parse())
+                    .collect::<Result<Vec<KeyChord>>>()?;
+                keymap.bind(context, action, chords);
+            }
+        }
+        keymap.check_conflicts()?;
+        Ok(keymap)
+    }
+
+    fn bind(&mut self, context: KeyContext, action: KeyAction, chords: Vec<KeyChord>) {
+        let bindings = self.bindings.entry(context).or_default();
+        match bindings.iter_mut().find(|(a, _)| *a == action) {
+            Some((_, existing)) => *existing = chords,
+            None => bindings.push((action, chords)),
+        }
+    }
+
+    fn check_conflicts(&self) -> Result<()> {
+        let mut conflicts = vec![];
+        for &context in KeyContext::ALL {
+            let mut seen: Vec<(KeyChord, KeyAction)> = vec![];
+            for (action, chord) in self.bindings_in(context) {
+                match seen.iter().find(|(c, _)| *c == chord) {
+                    Some((_, other)) if *other != action => conflicts.push(format!(
+                        "'{chord}' is bound to both {0}.{1} and {0}.{2}",
+                        context.name(),
+                        other.name(),
+                        action.name()
+                    )),
+                    Some(_) => {}
+                    None => seen.push((chord, action)),
+                }
+
+                // Bindings of a view would silently shadow a different global action
+                if context != KeyContext::Global
+                    && !context.is_modal()
+                    && let Some((global, _)) = self
+                        .bindings_in(KeyContext::Global)
+                        .find(|(a, c)| *c == chord && *a != action)
+                {
+                    conflicts.push(format!(
+                        "'{chord}' is bound to both global.{} and {}.{}",
+                        global.name(),
+                        context.name(),
+                        action.name()
+                    ));
+                }
+            }
+        }
+
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            bail!("Conflicting key bindings:\n  {}", conflicts.join("\n  "))
+        }
+    }
+
+    fn bindings_in(&self, context: KeyContext) -> impl Iterator<Item = (KeyAction, KeyChord)> + '_ {
+        self.bindings
+            .get(&context)
+            .into_iter()
+            .flatten()
+            .flat_map(|(action, chords)| chords.iter().map(|c| (*action, *c)))
+    }
+
+    /// All the bindings of a context, in the order they were defined. Actions that aren't bound
+    /// to any key are left out.
+    pub fn bindings(&self, context: KeyContext) -> impl Iterator<Item = (KeyAction, &[KeyChord])> {
+        self.bindings
+            .get(&context)
+            .into_iter()
+            .flatten()
+            .filter(|(_, chords)| !chords.is_empty())
+            .map(|(action, chords)| (*action, chords.as_slice()))
+    }
+
+    /// Find the action bound to a key in the given context, falling back to the global bindings
+    /// unless the context is a popup.
+    pub fn action_for(&self, context: KeyContext, key: &KeyEvent) -> Option<KeyAction> {
+        let chord = KeyChord::from(key);
+        let find = |ctx| {
+            self.bindings_in(ctx)
+                .find(|(_, c)| *c == chord)
+                .map(|(action, _)| action)
+        };
+        find(context).or_else(|| (!context.is_modal()).then(|| find(KeyContext::Global))?)
+    }
+
+    /// The keys bound to an action in the given context (or globally, unless it's a popup).
+    pub fn chords(&self, context: KeyContext, action: KeyAction) -> Vec<KeyChord> {
+        let fallback = (!context.is_modal()).then_some(KeyContext::Global);
+        std::iter::once(context)
+            .chain(fallback)
+            .filter_map(|ctx| self.bindings.get(&ctx))
+            .flatten()
+            .find(|(a, chords)| *a == action && !chords.is_empty())
+            .map(|(_, chords)| chords.clone())
+            .unwrap_or_default()
+    }
+
+    /// Build a hint line such as " SPACE play/pause • n next ", showing the first key bound to
+    /// each of the given actions. Actions that aren't bound to anything are left out.
+    pub fn hints(&self, context: KeyContext, hints: &[(&[KeyAction], &str)]) -> String {
+        let parts: Vec<String> = hints
+            .iter()
+            .filter_map(|(actions, label)| {
+                let keys: Vec<String> = actions
+                    .iter()
+                    .filter_map(|a| self.chords(context, *a).first().map(ToString::to_string))
+                    .collect();
+                (!keys.is_empty()).then(|| format!("{} {label}", keys.join(" ")))
+            })
+            .collect();
+        format!(" {} ", parts.join(" • "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use super::{KeyAction, KeyChord, KeyContext, Keymap, KeysConfig};
+
+    fn chord(s: &str) -> KeyChord {
+        s.parse().unwrap()
+    }
+
+    fn config(context: &str, action: &str, keys: &[&str]) -> KeysConfig {
+        let keys = keys.iter().map(ToString::to_string).collect();
+        [(context.to_owned(), [(action.to_owned(), keys)].into())].into()
+    }
+
+    fn error(config: &KeysConfig) -> String {
+        Keymap::from_config(config).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parse_chord_modifiers() {
+        assert_eq!(
+            chord("ctrl+c"),
+            KeyChord::new(KeyCode::Char('c'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            chord("Control+Alt+x"),
+            KeyChord::new(
+                KeyCode::Char('x'),
+                KeyModifiers::CONTROL | KeyModifiers::ALT
+            )
+        );
+        // Shift is part of the character
+        assert_eq!(chord("shift+a"), chord("A"));
+        assert_eq!(chord("shift+tab"), chord("backtab"));
+        assert_eq!(
+            chord("ctrl++"),
+            KeyChord::new(KeyCode::Char('+'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            chord("+"),
+            KeyChord::new(KeyCode::Char('+'), KeyModifiers::NONE)
+        );
+    }
+
+    #[test]
+    fn parse_chord_named_keys() {
+        assert_eq!(
+            chord("space"),
+            KeyChord::new(KeyCode::Char(' '), KeyModifiers::NONE)
+        );
+        assert_eq!(chord("Enter"), chord("return"));
+        assert_eq!(
+            chord("esc"),
+            KeyChord::new(KeyCode::Esc, KeyModifiers::NONE)
+        );
+        assert_eq!(
+            chord("PageDown"),
+            KeyChord::new(KeyCode::PageDown, KeyModifiers::NONE)
+        );
+        assert_eq!(
+            chord("f12"),
+            KeyChord::new(KeyCode::F(12), KeyModifiers::NONE)
+        );
+    }
+
+    #[test]
+    fn parse_chord_invalid() {
+        assert!("hyper+a".parse::<KeyChord>().is_err());
+        assert!("ctrl+".parse::<KeyChord>().is_err());
+        assert!("f13".parse::<KeyChord>().is_err());
+        assert!("nope".parse::<KeyChord>().is_err());
+        assert!("".parse::<KeyChord>().is_err());
+    }
+
+    #[test]
+    fn display_chord() {
+        assert_eq!(chord("ctrl+c").to_string(), "CTRL+c");
+        assert_eq!(chord("shift+tab").to_string(), "SHIFT+TAB");
+        assert_eq!(chord("space").to_string(), "SPACE");
+        assert_eq!(chord("up").to_string(), "↑");
+        // What's displayed can be written in the configuration
+        for s in [
+            "ctrl+alt+x",
+            "shift+tab",
+            "space",
+            "enter",
+            "delete",
+            "f5",
+            "ctrl++",
+        ] {
+            let parsed = chord(s);
+            assert_eq!(chord(&parsed.to_string()), parsed, "{s}");
+        }
+    }
+
+    #[test]
+    fn chord_from_key_event() {
+        let event = KeyEvent::new(KeyCode::Char('A'), KeyModifiers::SHIFT);
+        assert_eq!(KeyChord::from(&event), chord("A"));
+        let event = KeyEvent::new(KeyCode::Tab, KeyModifiers::SHIFT);
+        assert_eq!(KeyChord::from(&event), chord("shift+tab"));
+    }
+
+    #[test]
+    fn default_keymap_has_no_conflicts() {
+        assert!(Keymap::default().check_conflicts().is_ok());
+    }
+
+    #[test]
+    fn rebinding_replaces_the_default() {
+        let keymap = Keymap::from_config(&config("global", "next", &["N"])).unwrap();
+        let n = KeyEvent::new(KeyCode::Char('n'), KeyModifiers::NONE);
+        let shift_n = KeyEvent::new(KeyCode::Char('N'), KeyModifiers::SHIFT);
+        assert_eq!(keymap.action_for(KeyContext::Global, &n), None);
+        assert_eq!(
+            keymap.action_for(KeyContext::Queue, &shift_n),
+            Some(KeyAction::Next)
+        );
+    }
+
+    #[test]
+    fn conflict_within_a_context() {
+        assert_eq!(
+            error(&config("queue", "filter", &["j"])),
+            "Conflicting key bindings:\n  'j' is bound to both queue.down and queue.filter"
+        );
+    }
+
+    #[test]
+    fn view_binding_shadowing_a_global_one() {
+        assert_eq!(
+            error(&config("queue", "filter", &["n"])),
+            "Conflicting key bindings:\n  'n' is bound to both global.next and queue.filter"
+        );
+        // The alarm editor replaces the global bindings
+        assert!(Keymap::from_config(&config("alarm_editor", "save", &["n"])).is_ok());
+    }
+
+    #[test]
+    fn unknown_names() {
+        assert!(error(&config("global", "dance", &["x"])).starts_with("unknown action 'dance'"));
+        assert!(Keymap::from_config(&config("nowhere", "quit", &["x"])).is_err());
+        assert!(Keymap::from_config(&config("global", "quit", &["hyper+x"])).is_err());
+    }
+}
@@ -1,31 +1,98 @@
-use clap::{arg, command};
-use tracing::{error, info};
+use std::{path::Path, str::FromStr, time::Duration};
+
+use clap::{ArgMatches, Command, arg, command, value_parser};
+use crossterm::{
+    event::{DisableMouseCapture, EnableMouseCapture},
+    execute,
+};
+use ratatui_image::picker::Picker;
+use serde_derive::Deserialize;
+use tracing::{error, info, warn};
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::{EnvFilter, fmt::format::FmtSpan};
 
+mod alarm_editor;
+mod alarms;
 mod app;
+mod art;
+mod cli;
+mod config;
+mod fade;
+mod file_server;
+mod filter;
+mod http;
 mod input;
+mod keymap;
+mod media;
+mod menu;
+mod metrics;
+mod mqtt;
+mod pending;
+mod scene;
+mod snapshot;
 mod sonos;
+mod theme;
 mod view;
 
-use crate::{app::App, sonos::SpeakerState};
+use crate::{
+    alarms::Alarm,
+    app::App,
+    config::Config,
+    keymap::Keymap,
+    sonos::{ConnectError, SpeakerState},
+    theme::Theme,
+};
 
 #[derive(Debug)]
 pub enum State {
     Ready(Box<SpeakerState>),
-    Connecting,
+    /// Looking for speakers, with the names of the ones found so far
+    Connecting(Vec<String>),
+    Failed(ConnectError),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ViewMode {
     Queue,
     Favorites,
+    /// Every group at once
+    Overview,
+    Alarms,
+}
+
+impl FromStr for ViewMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "queue" => Ok(Self::Queue),
+            "favorites" => Ok(Self::Favorites),
+            "overview" => Ok(Self::Overview),
+            "alarms" => Ok(Self::Alarms),
+            _ => Err(format!(
+                "unknown view '{s}' (expected 'queue', 'favorites', 'overview' or 'alarms')"
+            )),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum Direction {
     Up,
     Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    /// How many items a move goes through a list: back for up and left, forward for down and right.
+    pub fn step(&self) -> isize {
+        match self {
+            Direction::Up | Direction::Left => -1,
+            Direction::Down | Direction::Right => 1,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -34,53 +101,329 @@ pub enum Action {
     Pause,
     Next,
     Prev,
-    NextSpeaker,
-    PrevSpeaker,
+    /// Select the group this many tabs away, wrapping around
+    CycleGroup(isize),
+    SelectGroup(usize),
     VolAdjust(i16),
+    SetVolume(u16),
+    /// Seek to the given position in the current track, in seconds
+    Seek(u32),
     SwitchView(ViewMode),
-    NavigateFavorites(Direction),
+    /// Move the selection of the queue by this many items
+    NavigateQueue(isize),
+    SelectQueueItem(usize),
+    PlayQueueItem(usize),
+    /// Move the selection of the favorites by this many items
+    NavigateFavorites(isize),
+    SelectFavorite(usize),
     PlayFavorite(usize),
+    /// Play one of the line-in or TV inputs on the selected group
+    PlayInput(usize),
+    /// Play an HTTP(S) URL straight away, or add it to the end of the queue
+    PlayUrl {
+        url: String,
+        enqueue: bool,
+    },
+    /// Play local files (or the audio files in directories), or add them to the end of the queue
+    PlayFiles {
+        paths: Vec<std::path::PathBuf>,
+        enqueue: bool,
+    },
+    /// Interrupt the selected group to play a clip, optionally at another volume, then resume what
+    /// it was doing
+    Announce {
+        url: String,
+        volume: Option<u16>,
+    },
+    /// Move what the selected group is playing (source, queue and position) to another group, and
+    /// select it. The selected group keeps playing unless `stop_source` is set.
+    TransferPlayback {
+        to: usize,
+        stop_source: bool,
+    },
+    /// Recall the scene of the given name from the configuration
+    ApplyScene(String),
+    /// Add the current layout to the configuration as a scene of the given name
+    SaveScene(String),
+    /// Move the selection of the alarms by this many items
+    NavigateAlarms(isize),
+    SelectAlarm(usize),
+    /// Enable or disable the alarm at the given position in the list
+    ToggleAlarm(usize),
+    /// Create the alarm, or update it if it already exists
+    SaveAlarm(Alarm),
+    /// Delete the alarm with this ID
+    DeleteAlarm(String),
+    /// Run an action against the group with the given coordinator instead of the selected one
+    ForGroup(String, Box<Action>),
+    /// Try to connect to the speakers again after a failure
+    Retry,
     Nop,
 }
 
+/// An action sent to the speakers.
+#[derive(Debug)]
+pub struct Request {
+    pub action: Action,
+    /// Given by the UI to find out when the action was handled, see `SpeakerState::handled`
+    pub seq: Option<u64>,
+}
+
+impl From<Action> for Request {
+    fn from(action: Action) -> Self {
+        Self { action, seq: None }
+    }
+}
+
 #[derive(Debug)]
 pub enum Update {
     NewState(Box<SpeakerState>),
+    /// A speaker was found while connecting
+    Discovered(String),
+    Failed(ConnectError),
+    /// Error or confirmation to display to the user
+    Notification(Notification),
     Nop,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationLevel {
+    Info,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub level: NotificationLevel,
+    pub message: String,
+}
+
+impl Notification {
+    pub fn info(message: impl Into<String>) -> Self {
+        Self {
+            level: NotificationLevel::Info,
+            message: message.into(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            level: NotificationLevel::Error,
+            message: message.into(),
+        }
+    }
+
+    /// How long the notification stays on screen
+    pub fn lifetime(&self) -> Duration {
+        match self.level {
+            NotificationLevel::Info => Duration::from_secs(3),
+            NotificationLevel::Error => Duration::from_secs(8),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
     human_panic::setup_panic!();
 
-    let _guard = init_logger();
-    info!("Welcome to Sinuous!");
-
     // Set the App with clap to accept Command Line Arguments
     let args = command!()
+        .arg(
+            arg!(
+                -c --config <file> "Path to the configuration file. Defaults to $XDG_CONFIG_HOME/sinuous/config.toml"
+            )
+            .required(false)
+            .global(true)
+            .value_parser(value_parser!(std::path::PathBuf))
+        )
         .arg(
             arg!(
                 -d --device <device> "Specify a speaker to connect to. Provide either an Ipv4 Address or a name to search for. Multiple values are possible by seperating them with a comma"
             )
             .required(false)
+            .global(true)
+        )
+        .arg(
+            arg!(
+                -g --group <name> "Name of the group (or of one of its speakers) to select on startup"
+            )
+            .required(false)
+            .global(true)
+        )
+        .arg(
+            arg!(
+                --view <view> "View to display on startup: 'queue', 'favorites', 'overview' or 'alarms'"
+            )
+            .required(false)
+            .value_parser(value_parser!(ViewMode))
+        )
+        .arg(
+            arg!(
+                --"volume-step" <step> "How much the volume changes on each key press"
+            )
+            .required(false)
+            .value_parser(value_parser!(i16))
+        )
+        .arg(
+            arg!(
+                --"poll-interval" <seconds> "Delay between two refreshes of the state of the speakers"
+            )
+            .required(false)
+            .value_parser(value_parser!(f64))
+        )
+        .arg(
+            arg!(
+                --"discovery-timeout" <seconds> "How long to wait for speakers to answer during discovery"
+            )
+            .required(false)
+            .value_parser(value_parser!(f64))
+        )
+        .arg(
+            arg!(
+                --"log-file" <file> "Where to write the logs. Defaults to sinuous.log in the temporary directory"
+            )
+            .required(false)
+            .value_parser(value_parser!(std::path::PathBuf))
+        )
+        .arg(
+            arg!(
+                --theme <name> "Colour theme: default, ocean, solarized, monochrome or high-contrast"
+            )
+            .required(false)
+        )
+        .arg(arg!(--"no-album-art" "Don't display the album art of the current track"))
+        .arg(
+            arg!(--fade <seconds> "Fade in when playing and out when pausing, over that many seconds")
+                .required(false)
+                .value_parser(value_parser!(f64))
+        )
+        .arg(
+            arg!(
+                --mqtt <broker> "Publish the state of every group to an MQTT broker and accept commands from it. Format: [user:password@]host[:port]"
+            )
+            .required(false)
+        )
+        .arg(
+            arg!(
+                --metrics <address> "Expose Prometheus metrics on http://<address>/metrics, e.g. 127.0.0.1:9100"
+            )
+            .required(false)
+            .value_parser(value_parser!(std::net::SocketAddr))
+        )
+        .subcommand(
+            Command::new("play-url")
+                .about("Play an HTTP(S) audio URL or radio stream on the selected group, and exit")
+                .arg(arg!(<url> "URL to play"))
+                .arg(arg!(--enqueue "Add the URL to the end of the queue instead of playing it now"))
+        )
+        .subcommand(
+            Command::new("announce")
+                .about("Play a clip on the selected group, then resume what it was playing, and exit")
+                .arg(arg!(<url> "URL of the clip to play"))
+                .arg(
+                    arg!(--volume <volume> "Volume to play the clip at (0-100)")
+                        .required(false)
+                        .value_parser(value_parser!(u16).range(0..=100))
+                )
+        )
+        .subcommand(
+            Command::new("scene")
+                .about("Recall a scene from the configuration file, or add one to it")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("apply")
+                        .about("Form the groups, set the volumes and start playing, and exit")
+                        .arg(arg!(<name> "Name of the scene"))
+                )
+                .subcommand(
+                    Command::new("save")
+                        .about("Save the groups, the volumes and what the selected group plays, and exit")
+                        .arg(arg!(<name> "Name of the scene"))
+                )
+        )
+        .subcommand(
+            Command::new("play-file")
+                .about("Play local audio files on the selected group, serving them until interrupted")
+                .arg(
+                    arg!(<path> ... "Files, or directories to play the audio files of")
+                        .value_parser(value_parser!(std::path::PathBuf))
+                )
+        )
+        .subcommand(
+            Command::new("enqueue-file")
+                .about("Add local audio files to the end of the queue, serving them until interrupted")
+                .arg(
+                    arg!(<path> ... "Files, or directories to add the audio files of")
+                        .value_parser(value_parser!(std::path::PathBuf))
+                )
         )
         .get_matches();
 
-    let app = App::new(args);
+    let (config, keymap, theme) = match load_settings(&args) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("Error: {err:#}");
+            std::process::exit(1);
+        }
+    };
+
+    let _guard = init_logger(&config.log_file);
+
+    // Commands run without the UI
+    if let Some((name, sub_args)) = args.subcommand() {
+        let result = match cli::subcommand_action(name, sub_args) {
+            Ok(action) => cli::run(&config, action).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            eprintln!("Error: {err:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    info!("Welcome to Sinuous!");
+
     // Initialize the terminal user interface.
     let mut terminal = ratatui::init();
+    if let Err(err) = execute!(std::io::stdout(), EnableMouseCapture) {
+        warn!(%err, "Failed to enable mouse support");
+    }
+    // Find out how images can be displayed. This has to be done before reading any event.
+    let picker = config.album_art.then(|| {
+        Picker::from_query_stdio().unwrap_or_else(|err| {
+            warn!(%err, "Failed to query the terminal for graphics support");
+            Picker::halfblocks()
+        })
+    });
+
+    let app = App::new(config, keymap, theme, picker);
 
     if let Err(err) = app.run(&mut terminal).await {
         error!("Main loop exited with error: {}", err);
     } else {
         info!("Bye!");
     }
+    let _ = execute!(std::io::stdout(), DisableMouseCapture);
     ratatui::restore();
 }
 
-fn init_logger() -> WorkerGuard {
+/// Load the configuration, and build the keymap and theme it describes.
+fn load_settings(args: &ArgMatches) -> anyhow::Result<(Config, Keymap, Theme)> {
+    let config = Config::from_args(args)?;
+    let keymap = Keymap::from_config(&config.keys)?;
+    let theme = Theme::from_config(&config.theme)?;
+    Ok((config, keymap, theme))
+}
+
+fn init_logger(log_file: &Path) -> WorkerGuard {
     // Initialize logging framework
-    let rolling = tracing_appender::rolling::never(std::env::temp_dir(), "sinuous.log");
+    let dir = log_file
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let file_name = log_file.file_name().unwrap_or("sinuous.log".as_ref());
+    let rolling = tracing_appender::rolling::never(dir, file_name);
     let (appender, guard) = tracing_appender::non_blocking(rolling);
     tracing_subscriber::fmt::SubscriberBuilder::default()
         .with_writer(appender)
@@ -0,0 +1,233 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::{Result, bail};
+use lofty::prelude::*;
+use tracing::{debug, warn};
+
+use crate::sonos::xml_escape;
+
+/// Something that can be played or enqueued: a URI, with the DIDL-Lite metadata describing it.
+#[derive(Debug, Clone)]
+pub struct MediaItem {
+    pub uri: String,
+    pub metadata: String,
+    pub title: String,
+    /// Live streams can't be added to the queue
+    pub is_stream: bool,
+}
+
+/// Details of an item, to build its metadata.
+#[derive(Debug, Default)]
+pub struct ItemInfo<'a> {
+    pub title: &'a str,
+    pub creator: Option<&'a str>,
+    pub album: Option<&'a str>,
+    pub album_art: Option<&'a str>,
+    /// Length of the track, in seconds
+    pub duration: Option<u32>,
+    pub is_stream: bool,
+}
+
+/// Minimal DIDL-Lite metadata for an item that isn't provided by a music service.
+pub fn didl(uri: &str, info: &ItemInfo) -> String {
+    let class = if info.is_stream {
+        "object.item.audioItem.audioBroadcast"
+    } else {
+        "object.item.audioItem.musicTrack"
+    };
+    let mut item = format!("<dc:title>{}</dc:title>", xml_escape(info.title));
+    if let Some(creator) = info.creator {
+        item.push_str(&format!("<dc:creator>{}</dc:creator>", xml_escape(creator)));
+    }
+    if let Some(album) = info.album {
+        item.push_str(&format!("<upnp:album>{}</upnp:album>", xml_escape(album)));
+    }
+    if let Some(art) = info.album_art {
+        item.push_str(&format!(
+            "<upnp:albumArtURI>{}</upnp:albumArtURI>",
+            xml_escape(art)
+        ));
+    }
+    item.push_str(&format!("<upnp:class>{class}</upnp:class>"));
+    let duration = info.duration.map_or(String::new(), |secs| {
+        format!(
+            r#" duration="{}:{:02}:{:02}""#,
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        )
+    });
+    item.push_str(&format!(
+        r#"<res protocolInfo="http-get:*:*:*"{duration}>{}</res>"#,
+        xml_escape(uri)
+    ));
+
+    didl_lite(&format!(
+        r#"<item id="-1" parentID="-1" restricted="true">{item}</item>"#
+    ))
+}
+
+/// Wrap DIDL-Lite items in a document, with the namespaces the speakers use.
+pub fn didl_lite(items: &str) -> String {
+    format!(
+        r#"<DIDL-Lite xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/" xmlns:r="urn:schemas-rinconnetworks-com:metadata-1-0/" xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/">{items}</DIDL-Lite>"#
+    )
+}
+
+/// Find out what an HTTP(S) URL points to, and how the speakers should be asked to play it.
+///
+/// MP3 radio streams have to be played with the `x-rincon-mp3radio` scheme, or the speakers give
+/// up on them after a while. They're told apart from files by asking the server: streams have no
+/// length, and usually come with a station name.
+pub async fn url_item(url: &str) -> Result<MediaItem> {
+    let Some(rest) = url
+        .strip_prefix("http://")
+        .or_else(|| url.strip_prefix("https://"))
+    else {
+        bail!("Only http:// and https:// URLs can be played");
+    };
+
+    let probe = match probe(url).await {
+        Ok(probe) => probe,
+        Err(err) => {
+            // The speakers may still be able to reach it
+            warn!(%url, "Failed to probe URL: {err:#}");
+            Probe::default()
+        }
+    };
+    debug!(%url, ?probe, "Probed URL");
+
+    let file_name = rest
+        .split(['?', '#'])
+        .next()
+        .and_then(|path| path.rsplit('/').next())
+        .filter(|name| !name.is_empty())
+        .unwrap_or(url);
+    let title = probe
+        .station
+        .clone()
+        .unwrap_or_else(|| file_name.to_owned());
+
+    let is_mp3 = probe
+        .content_type
+        .as_deref()
+        .is_some_and(|t| t == "audio/mpeg" || t == "audio/mp3");
+    let is_stream = probe.content_length.is_none() && probe.content_type.is_some();
+    let uri = if is_mp3 && is_stream {
+        format!("x-rincon-mp3radio://{rest}")
+    } else {
+        url.to_owned()
+    };
+
+    let metadata = didl(
+        &uri,
+        &ItemInfo {
+            title: &title,
+            is_stream,
+            ..ItemInfo::default()
+        },
+    );
+    Ok(MediaItem {
+        uri,
+        metadata,
+        title,
+        is_stream,
+    })
+}
+
+/// Describe a local file from its tags, falling back to its name. `url` is where the speakers can
+/// fetch it from.
+pub async fn file_item(path: &Path, url: String) -> MediaItem {
+    let tags = {
+        let path = path.to_owned();
+        tokio::task::spawn_blocking(move || read_tags(&path)).await
+    };
+    let tags = match tags {
+        Ok(Ok(tags)) => tags,
+        Ok(Err(err)) => {
+            warn!(path = %path.display(), "Failed to read tags: {err:#}");
+            Tags::default()
+        }
+        Err(err) => {
+            warn!(path = %path.display(), "Failed to read tags: {err}");
+            Tags::default()
+        }
+    };
+    let title = tags.title.unwrap_or_else(|| {
+        path.file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| url.clone())
+    });
+
+    let metadata = didl(
+        &url,
+        &ItemInfo {
+            title: &title,
+            creator: tags.artist.as_deref(),
+            album: tags.album.as_deref(),
+            duration: tags.duration,
+            ..ItemInfo::default()
+        },
+    );
+    MediaItem {
+        uri: url,
+        metadata,
+        title,
+        is_stream: false,
+    }
+}
+
+#[derive(Debug, Default)]
+struct Tags {
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    duration: Option<u32>,
+}
+
+fn read_tags(path: &Path) -> Result<Tags> {
+    let file = lofty::read_from_path(path)?;
+    let duration = file.properties().duration().as_secs() as u32;
+    let mut tags = Tags {
+        duration: (duration > 0).then_some(duration),
+        ..Tags::default()
+    };
+    if let Some(tag) = file.primary_tag().or_else(|| file.first_tag()) {
+        tags.title = tag.title().map(|s| s.into_owned());
+        tags.artist = tag.artist().map(|s| s.into_owned());
+        tags.album = tag.album().map(|s| s.into_owned());
+    }
+    Ok(tags)
+}
+
+#[derive(Debug, Default)]
+struct Probe {
+    content_type: Option<String>,
+    content_length: Option<u64>,
+    /// Name of the station, for Icecast / Shoutcast streams
+    station: Option<String>,
+}
+
+async fn probe(url: &str) -> Result<Probe> {
+    let response = reqwest::Client::new()
+        .get(url)
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await?
+        .error_for_status()?;
+    let headers = response.headers();
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_owned())
+            .filter(|v| !v.is_empty())
+    };
+    // Don't download the body, which never ends for streams
+    Ok(Probe {
+        content_type: header("content-type")
+            .map(|t| t.split(';').next().unwrap_or_default().to_ascii_lowercase()),
+        content_length: response.content_length(),
+        station: header("icy-name"),
+    })
+}
@@ -0,0 +1,87 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::Action;
+
+/// What the main loop should do after a key was handled by a menu or a prompt.
+pub enum MenuResult {
+    Continue,
+    Select(Action),
+    Cancel,
+}
+
+/// Popup listing a few choices, each running an action when picked.
+#[derive(Debug)]
+pub struct Menu {
+    pub title: String,
+    items: Vec<(String, Action)>,
+    pub selected: usize,
+}
+
+impl Menu {
+    pub fn new(title: impl Into<String>, items: Vec<(String, Action)>) -> Self {
+        Self {
+            title: title.into(),
+            items,
+            selected: 0,
+        }
+    }
+
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.items.iter().map(|(label, _)| label.as_str())
+    }
+
+    pub fn handle_key(&mut self, key: &KeyEvent) -> MenuResult {
+        match key.code {
+            KeyCode::Esc => return MenuResult::Cancel,
+            KeyCode::Enter if self.selected < self.items.len() => {
+                return MenuResult::Select(self.items.swap_remove(self.selected).1);
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected = (self.selected + 1).min(self.items.len().saturating_sub(1));
+            }
+            _ => {}
+        }
+        MenuResult::Continue
+    }
+}
+
+/// Popup asking for a line of text, e.g. a URL, which is turned into an action once submitted.
+#[derive(Debug)]
+pub struct Prompt {
+    pub title: String,
+    pub input: String,
+    submit: fn(String) -> Action,
+}
+
+impl Prompt {
+    pub fn new(title: impl Into<String>, submit: fn(String) -> Action) -> Self {
+        Self {
+            title: title.into(),
+            input: String::new(),
+            submit,
+        }
+    }
+
+    pub fn handle_key(&mut self, key: &KeyEvent) -> MenuResult {
+        match key.code {
+            KeyCode::Esc => return MenuResult::Cancel,
+            KeyCode::Enter if !self.input.trim().is_empty() => {
+                let input = std::mem::take(&mut self.input);
+                return MenuResult::Select((self.submit)(input.trim().to_owned()));
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(c)
+                if !key
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                self.input.push(c);
+            }
+            _ => {}
+        }
+        MenuResult::Continue
+    }
+}
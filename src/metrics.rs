@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+    sync::watch,
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{http, sonos::GroupStatus};
+
+/// Metrics collected by `SonosService` about the UPnP actions it performs and the speakers it
+/// talks to.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    actions: Mutex<BTreeMap<&'static str, ActionStats>>,
+    // Reachability of each speaker, indexed by UUID
+    speakers: Mutex<BTreeMap<String, SpeakerReachability>>,
+}
+
+/// Extracts the value of a per-group gauge from the status of a group
+type GroupGauge = fn(&GroupStatus) -> f64;
+
+#[derive(Debug, Default)]
+struct ActionStats {
+    calls: u64,
+    errors: u64,
+    duration: Duration,
+}
+
+#[derive(Debug)]
+struct SpeakerReachability {
+    name: String,
+    reachable: bool,
+}
+
+impl Metrics {
+    /// Run a UPnP action, recording its outcome and how long it took.
+    pub async fn track<T, E>(
+        &self,
+        action: &'static str,
+        fut: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, E> {
+        let start = Instant::now();
+        let result = fut.await;
+        let elapsed = start.elapsed();
+
+        let mut actions = self.actions.lock().expect("metrics lock poisoned");
+        let stats = actions.entry(action).or_default();
+        stats.calls += 1;
+        stats.duration += elapsed;
+        if result.is_err() {
+            stats.errors += 1;
+        }
+        result
+    }
+
+    pub fn set_reachable(&self, uuid: &str, name: Option<String>, reachable: bool) {
+        let mut speakers = self.speakers.lock().expect("metrics lock poisoned");
+        let entry = speakers
+            .entry(uuid.to_string())
+            .or_insert_with(|| SpeakerReachability {
+                name: uuid.to_string(),
+                reachable,
+            });
+        if let Some(name) = name {
+            entry.name = name;
+        }
+        entry.reachable = reachable;
+    }
+
+    /// Render all the metrics in the Prometheus text exposition format.
+    fn render(&self, groups: &[GroupStatus]) -> String {
+        let mut out = String::new();
+
+        let group_gauges: [(&str, &str, GroupGauge); 5] = [
+            (
+                "sinuous_group_volume",
+                "Volume of the group coordinator.",
+                |g| f64::from(g.volume),
+            ),
+            (
+                "sinuous_group_playing",
+                "Whether the group is currently playing (1) or not (0).",
+                |g| if g.playing { 1.0 } else { 0.0 },
+            ),
+            (
+                "sinuous_group_elapsed_seconds",
+                "Elapsed time of the current track.",
+                |g| f64::from(g.elapsed),
+            ),
+            (
+                "sinuous_group_duration_seconds",
+                "Duration of the current track.",
+                |g| f64::from(g.duration),
+            ),
+            (
+                "sinuous_group_queue_length",
+                "Number of tracks in the queue of the group.",
+                |g| g.queue_length as f64,
+            ),
+        ];
+        for (name, help, value) in group_gauges {
+            write_header(&mut out, name, help, "gauge");
+            for group in groups {
+                let _ = writeln!(
+                    out,
+                    "{name}{{group=\"{}\",coordinator=\"{}\"}} {}",
+                    escape_label(&group.name),
+                    escape_label(&group.coordinator),
+                    value(group)
+                );
+            }
+        }
+
+        let speakers = self.speakers.lock().expect("metrics lock poisoned");
+        write_header(
+            &mut out,
+            "sinuous_speaker_reachable",
+            "Whether the speaker answered the last reachability check.",
+            "gauge",
+        );
+        for (uuid, speaker) in speakers.iter() {
+            let _ = writeln!(
+                out,
+                "sinuous_speaker_reachable{{speaker=\"{}\",uuid=\"{}\"}} {}",
+                escape_label(&speaker.name),
+                escape_label(uuid),
+                u8::from(speaker.reachable)
+            );
+        }
+        drop(speakers);
+
+        let actions = self.actions.lock().expect("metrics lock poisoned");
+        write_header(
+            &mut out,
+            "sinuous_upnp_action_calls_total",
+            "Number of UPnP actions performed.",
+            "counter",
+        );
+        for (action, stats) in actions.iter() {
+            let _ = writeln!(
+                out,
+                "sinuous_upnp_action_calls_total{{action=\"{action}\"}} {}",
+                stats.calls
+            );
+        }
+        write_header(
+            &mut out,
+            "sinuous_upnp_action_errors_total",
+            "Number of UPnP actions that failed.",
+            "counter",
+        );
+        for (action, stats) in actions.iter() {
+            let _ = writeln!(
+                out,
+                "sinuous_upnp_action_errors_total{{action=\"{action}\"}} {}",
+                stats.errors
+            );
+        }
+        write_header(
+            &mut out,
+            "sinuous_upnp_action_duration_seconds",
+            "Time spent performing UPnP actions.",
+            "summary",
+        );
+        for (action, stats) in actions.iter() {
+            let _ = writeln!(
+                out,
+                "sinuous_upnp_action_duration_seconds_sum{{action=\"{action}\"}} {}",
+                stats.duration.as_secs_f64()
+            );
+            let _ = writeln!(
+                out,
+                "sinuous_upnp_action_duration_seconds_count{{action=\"{action}\"}} {}",
+                stats.calls
+            );
+        }
+
+        out
+    }
+}
+
+/// Minimal HTTP server exposing the metrics on `/metrics`.
+pub struct MetricsServer {
+    addr: SocketAddr,
+    metrics: Arc<Metrics>,
+    status_rx: watch::Receiver<Vec<GroupStatus>>,
+}
+
+impl MetricsServer {
+    pub fn new(
+        addr: SocketAddr,
+        metrics: Arc<Metrics>,
+        status_rx: watch::Receiver<Vec<GroupStatus>>,
+    ) -> Self {
+        Self {
+            addr,
+            metrics,
+            status_rx,
+        }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            if let Err(err) = self.inner_loop().await {
+                error!(%err, "Metrics server error");
+            }
+        });
+    }
+
+    async fn inner_loop(self) -> Result<()> {
+        let listener = TcpListener::bind(self.addr)
+            .await
+            .with_context(|| format!("Failed to bind metrics server to {}", self.addr))?;
+        info!("Serving metrics on http://{}/metrics", self.addr);
+
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            debug!(%peer, "Metrics request");
+            let body = self.metrics.render(&self.status_rx.borrow());
+            tokio::spawn(async move {
+                if let Err(e) = serve(stream, body).await {
+                    warn!("Failed to serve metrics: {}", e);
+                }
+            });
+        }
+    }
+}
+
+async fn serve(mut stream: TcpStream, body: String) -> Result<()> {
+    let request = http::read_request(&mut stream).await?;
+    let response = match (request.method.as_str(), request.target.as_str()) {
+        ("GET", "/metrics") => format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        ),
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+fn write_header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
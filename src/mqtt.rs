@@ -0,0 +1,349 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Packet, QoS};
+use serde_json::json;
+use tokio::{
+    select,
+    sync::{
+        mpsc::{self, Sender, UnboundedSender},
+        watch,
+    },
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{Action, Request, sonos::GroupStatus};
+
+const TOPIC_PREFIX: &str = "sinuous";
+const DISCOVERY_PREFIX: &str = "homeassistant";
+
+/// Bridge between the Sonos groups and an MQTT broker.
+///
+/// The state of each group is published (retained) to `sinuous/<group>/state` as JSON, and
+/// commands are accepted on `sinuous/<group>/command` (`play`, `pause`, `next`, `prev`,
+/// `volume_up`, `volume_down`, by the configured volume step) and `sinuous/<group>/volume/set`
+/// (absolute volume). Home Assistant discovery payloads are published for each group so they show
+/// up as devices automatically.
+pub struct MqttBridge {
+    client: AsyncClient,
+    // Moved to its own task once the bridge is started
+    eventloop: Option<EventLoop>,
+    status_rx: watch::Receiver<Vec<GroupStatus>>,
+    cmd_tx: UnboundedSender<Request>,
+    volume_step: i16,
+    // Last status published for each group, indexed by topic name
+    published: BTreeMap<String, GroupStatus>,
+}
+
+impl MqttBridge {
+    pub fn new(
+        broker: &str,
+        status_rx: watch::Receiver<Vec<GroupStatus>>,
+        cmd_tx: UnboundedSender<Request>,
+        volume_step: i16,
+    ) -> Result<Self> {
+        let (credentials, address) = match broker.rsplit_once('@') {
+            Some((credentials, address)) => (Some(credentials), address),
+            None => (None, broker),
+        };
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .with_context(|| format!("Invalid MQTT broker port: {port}"))?,
+            ),
+            None => (address, 1883),
+        };
+
+        let client_id = format!("sinuous-{}", std::process::id());
+        let mut options = MqttOptions::new(client_id, host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_last_will(LastWill::new(
+            availability_topic(),
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+        if let Some(credentials) = credentials {
+            let (user, password) = credentials.split_once(':').unwrap_or((credentials, ""));
+            options.set_credentials(user, password);
+        }
+
+        let (client, eventloop) = AsyncClient::new(options, 64);
+        Ok(Self {
+            client,
+            eventloop: Some(eventloop),
+            status_rx,
+            cmd_tx,
+            volume_step,
+            published: BTreeMap::new(),
+        })
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            if let Err(err) = self.inner_loop().await {
+                error!(%err, "MQTT error");
+            }
+        });
+    }
+
+    async fn inner_loop(mut self) -> Result<()> {
+        // The event loop needs to be polled continuously for the client to make progress, so it
+        // runs in its own task and forwards incoming messages to us.
+        let eventloop = self
+            .eventloop
+            .take()
+            .context("MQTT bridge already started")?;
+        let (incoming_tx, mut incoming_rx) = mpsc::channel(16);
+        tokio::spawn(poll_eventloop(eventloop, self.client.clone(), incoming_tx));
+
+        loop {
+            select! {
+                changed = self.status_rx.changed() => {
+                    if changed.is_err() {
+                        warn!("Group status channel was closed: exiting MQTT bridge");
+                        break;
+                    }
+                    let statuses = self.status_rx.borrow_and_update().clone();
+                    self.publish_statuses(statuses).await?;
+                }
+                incoming = incoming_rx.recv() => match incoming {
+                    Some(Incoming::Connected) => {
+                        // Retained messages may have been lost if the broker restarted, so
+                        // publish everything again.
+                        self.published.clear();
+                        let statuses = self.status_rx.borrow().clone();
+                        self.publish_statuses(statuses).await?;
+                    }
+                    Some(Incoming::Message(topic, payload)) => {
+                        if let Some(cmd) = self.parse_command(&topic, &payload) {
+                            self.cmd_tx.send(cmd.into())?;
+                        }
+                    }
+                    None => {
+                        warn!("MQTT event loop exited: exiting MQTT bridge");
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn publish_statuses(&mut self, statuses: Vec<GroupStatus>) -> Result<()> {
+        for status in statuses {
+            let group = slugify(&status.name);
+            if self.published.get(&group) == Some(&status) {
+                continue;
+            }
+            if !self.published.contains_key(&group) {
+                publish_discovery(&self.client, &group, &status).await?;
+            }
+            let payload = serde_json::to_vec(&status)?;
+            self.client
+                .publish(state_topic(&group), QoS::AtLeastOnce, true, payload)
+                .await?;
+            self.published.insert(group, status);
+        }
+        Ok(())
+    }
+
+    fn parse_command(&self, topic: &str, payload: &[u8]) -> Option<Action> {
+        let payload = String::from_utf8_lossy(payload);
+        let payload = payload.trim();
+        let (group, command) = topic
+            .strip_prefix(TOPIC_PREFIX)?
+            .strip_prefix('/')?
+            .split_once('/')?;
+        let Some(status) = self.published.get(group) else {
+            warn!("Received MQTT command for unknown group {group}");
+            return None;
+        };
+
+        let action = match (command, payload) {
+            ("command", "play") => Action::Play,
+            ("command", "pause") => Action::Pause,
+            ("command", "next") => Action::Next,
+            ("command", "prev") => Action::Prev,
+            ("command", "volume_up") => Action::VolAdjust(self.volume_step),
+            ("command", "volume_down") => Action::VolAdjust(-self.volume_step),
+            ("announce", payload) => match parse_announcement(payload) {
+                Some((url, volume)) => Action::Announce { url, volume },
+                None => {
+                    warn!("Invalid announcement received on {topic}: {payload}");
+                    return None;
+                }
+            },
+            ("volume/set", volume) => match volume.parse::<f64>() {
+                Ok(v) => Action::SetVolume(v.clamp(0.0, 100.0).round() as u16),
+                Err(_) => {
+                    warn!("Invalid volume received on {topic}: {volume}");
+                    return None;
+                }
+            },
+            _ => {
+                warn!("Unknown MQTT command on {topic}: {payload}");
+                return None;
+            }
+        };
+        debug!(?action, group, "Received MQTT command");
+        Some(Action::ForGroup(
+            status.coordinator.clone(),
+            Box::new(action),
+        ))
+    }
+}
+
+/// An announcement is either just the URL of the clip, or `{"url": "...", "volume": 40}`.
+fn parse_announcement(payload: &str) -> Option<(String, Option<u16>)> {
+    if !payload.starts_with('{') {
+        return (!payload.is_empty()).then(|| (payload.to_owned(), None));
+    }
+    let json: serde_json::Value = serde_json::from_str(payload).ok()?;
+    let url = json.get("url")?.as_str()?.to_owned();
+    let volume = match json.get("volume") {
+        Some(volume) => Some(volume.as_f64()?.clamp(0.0, 100.0).round() as u16),
+        None => None,
+    };
+    Some((url, volume))
+}
+
+enum Incoming {
+    Connected,
+    Message(String, Vec<u8>),
+}
+
+async fn poll_eventloop(mut eventloop: EventLoop, client: AsyncClient, tx: Sender<Incoming>) {
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!("Connected to MQTT broker");
+                // The session is not persistent, so subscriptions need to be renewed on every
+                // connection. Use the non-blocking variants as we are the ones draining the
+                // request queue.
+                let subscriptions = [
+                    format!("{TOPIC_PREFIX}/+/command"),
+                    format!("{TOPIC_PREFIX}/+/volume/set"),
+                    format!("{TOPIC_PREFIX}/+/announce"),
+                ];
+                for topic in subscriptions {
+                    if let Err(e) = client.try_subscribe(topic, QoS::AtLeastOnce) {
+                        warn!("Failed to subscribe to MQTT commands: {}", e);
+                    }
+                }
+                if let Err(e) =
+                    client.try_publish(availability_topic(), QoS::AtLeastOnce, true, "online")
+                {
+                    warn!("Failed to publish MQTT availability: {}", e);
+                }
+                if tx.send(Incoming::Connected).await.is_err() {
+                    break;
+                }
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                let msg = Incoming::Message(publish.topic, publish.payload.to_vec());
+                if tx.send(msg).await.is_err() {
+                    break;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                // The event loop reconnects automatically on the next poll
+                warn!("MQTT connection error: {}", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    }
+}
+
+/// Publish the Home Assistant discovery payloads for a group.
+async fn publish_discovery(client: &AsyncClient, group: &str, status: &GroupStatus) -> Result<()> {
+    let node_id = format!("sinuous_{group}");
+    let device = json!({
+        "identifiers": [format!("sinuous_{}", status.coordinator)],
+        "name": status.name,
+        "manufacturer": "Sonos",
+    });
+    let base = |name: &str, object_id: &str| {
+        json!({
+            "name": name,
+            "unique_id": format!("sinuous_{}_{object_id}", status.coordinator),
+            "availability_topic": availability_topic(),
+            "device": device,
+        })
+    };
+
+    let mut playing = base("Playing", "playing");
+    playing["state_topic"] = json!(state_topic(group));
+    playing["value_template"] = json!("{{ 'ON' if value_json.playing else 'OFF' }}");
+    playing["command_topic"] = json!(command_topic(group));
+    playing["payload_on"] = json!("play");
+    playing["payload_off"] = json!("pause");
+
+    let mut next = base("Next", "next");
+    next["command_topic"] = json!(command_topic(group));
+    next["payload_press"] = json!("next");
+
+    let mut prev = base("Previous", "prev");
+    prev["command_topic"] = json!(command_topic(group));
+    prev["payload_press"] = json!("prev");
+
+    let mut volume = base("Volume", "volume");
+    volume["state_topic"] = json!(state_topic(group));
+    volume["value_template"] = json!("{{ value_json.volume }}");
+    volume["command_topic"] = json!(volume_topic(group));
+    volume["min"] = json!(0);
+    volume["max"] = json!(100);
+
+    let mut now_playing = base("Now playing", "now_playing");
+    now_playing["state_topic"] = json!(state_topic(group));
+    now_playing["value_template"] = json!("{{ value_json.title or 'Nothing' }}");
+    now_playing["json_attributes_topic"] = json!(state_topic(group));
+
+    let configs = [
+        ("switch", "playing", playing),
+        ("button", "next", next),
+        ("button", "prev", prev),
+        ("number", "volume", volume),
+        ("sensor", "now_playing", now_playing),
+    ];
+    for (component, object_id, config) in configs {
+        let topic = format!("{DISCOVERY_PREFIX}/{component}/{node_id}/{object_id}/config");
+        client
+            .publish(topic, QoS::AtLeastOnce, true, serde_json::to_vec(&config)?)
+            .await?;
+    }
+    Ok(())
+}
+
+fn availability_topic() -> String {
+    format!("{TOPIC_PREFIX}/status")
+}
+
+fn state_topic(group: &str) -> String {
+    format!("{TOPIC_PREFIX}/{group}/state")
+}
+
+fn command_topic(group: &str) -> String {
+    format!("{TOPIC_PREFIX}/{group}/command")
+}
+
+fn volume_topic(group: &str) -> String {
+    format!("{TOPIC_PREFIX}/{group}/volume/set")
+}
+
+/// Turn a group name into something usable as an MQTT topic level, e.g.
+/// "Living Room + Kitchen" becomes "living_room_kitchen".
+fn slugify(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('_') {
+            slug.push('_');
+        }
+    }
+    slug.trim_end_matches('_').to_string()
+}
@@ -0,0 +1,133 @@
+use std::time::{Duration, Instant};
+
+use crate::{Action, Notification, sonos::SpeakerState};
+
+/// How long to show a change the speakers haven't confirmed before giving up on it
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Changes shown in the UI as soon as the keys are pressed, before the speakers confirm them.
+///
+/// Each command sent gets the next number, and a prediction is settled by the first state that
+/// reflects the command (see `SpeakerState::handled`): if the speakers didn't take the change, the
+/// state they report wins and the user is told.
+#[derive(Default)]
+pub struct Predictions {
+    /// Number of the last command sent
+    seq: u64,
+    playing: Option<Prediction<bool>>,
+    volume: Option<Prediction<u16>>,
+    favorite: Option<Prediction<usize>>,
+}
+
+struct Prediction<T> {
+    value: T,
+    /// What it was before the first of the commands being predicted
+    previous: T,
+    /// Number of the last of the commands being predicted
+    seq: u64,
+    sent: Instant,
+}
+
+impl<T: Copy> Prediction<T> {
+    /// Predict another change, on top of the one that may already be pending.
+    fn update(prediction: &mut Option<Self>, value: T, current: T, seq: u64) {
+        let previous = prediction.as_ref().map_or(current, |p| p.previous);
+        *prediction = Some(Self {
+            value,
+            previous,
+            seq,
+            sent: Instant::now(),
+        });
+    }
+
+    /// Whether the state shows the outcome of the commands, or they're taking too long.
+    fn is_settled(&self, state: &SpeakerState) -> bool {
+        state.handled >= self.seq || self.sent.elapsed() > TIMEOUT
+    }
+}
+
+impl Predictions {
+    /// Give the next command its number, to be sent along with it.
+    pub fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    /// Predict the outcome of the command numbered `seq`, about to be sent, and show it right away.
+    pub fn record(&mut self, action: &Action, seq: u64, state: &mut SpeakerState) {
+        match action {
+            Action::Play => Prediction::update(&mut self.playing, true, state.is_playing, seq),
+            Action::Pause => Prediction::update(&mut self.playing, false, state.is_playing, seq),
+            Action::VolAdjust(v) => {
+                let volume = (i32::from(state.current_volume) + i32::from(*v)).clamp(0, 100);
+                Prediction::update(&mut self.volume, volume as u16, state.current_volume, seq);
+            }
+            Action::SetVolume(v) => {
+                let volume = (*v).min(100);
+                Prediction::update(&mut self.volume, volume, state.current_volume, seq);
+            }
+            Action::SelectFavorite(index) if *index < state.favorites.len() => {
+                Prediction::update(&mut self.favorite, *index, state.selected_favorite, seq);
+            }
+            Action::NavigateFavorites(offset) => {
+                let index = (state.selected_favorite.saturating_add_signed(*offset))
+                    .min(state.favorites.len().saturating_sub(1));
+                Prediction::update(&mut self.favorite, index, state.selected_favorite, seq);
+            }
+            // The predictions were about the group that was selected
+            Action::SelectGroup(_) | Action::CycleGroup(_) => {
+                self.playing = None;
+                self.volume = None;
+                self.favorite = None;
+            }
+            _ => return,
+        }
+        self.apply(state);
+    }
+
+    /// Reconcile with a new state from the speakers, before showing it. Returns what couldn't be
+    /// done, if anything.
+    pub fn settle(&mut self, state: &mut SpeakerState) -> Vec<Notification> {
+        let mut notifications = vec![];
+
+        if let Some(playing) = self.playing.take_if(|p| p.is_settled(state))
+            && state.is_playing != playing.value
+            && state.is_playing == playing.previous
+        {
+            notifications.push(Notification::error(if playing.value {
+                "Couldn't resume playback"
+            } else {
+                "Couldn't pause"
+            }));
+        }
+        if let Some(volume) = self.volume.take_if(|p| p.is_settled(state))
+            && state.current_volume != volume.value
+            && state.current_volume == volume.previous
+        {
+            // e.g. the volume is at its limit already
+            notifications.push(Notification::info(format!(
+                "The volume stayed at {}",
+                state.current_volume
+            )));
+        }
+        self.favorite.take_if(|p| p.is_settled(state));
+
+        self.apply(state);
+        notifications
+    }
+
+    fn apply(&self, state: &mut SpeakerState) {
+        if let Some(playing) = &self.playing {
+            state.is_playing = playing.value;
+        }
+        if let Some(volume) = &self.volume {
+            state.current_volume = volume.value;
+        }
+        if let Some(favorite) = &self.favorite {
+            state.selected_favorite = favorite.value;
+        }
+        state.pending.playing = self.playing.is_some();
+        state.pending.volume = self.volume.is_some();
+        state.pending.favorite = self.favorite.is_some();
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::{Context, Result, ensure};
+use serde_derive::{Deserialize, Serialize};
+
+/// Named scenes from the configuration file.
+pub type ScenesConfig = BTreeMap<String, Scene>;
+
+/// A layout of the speakers to recall in one go: which rooms are grouped together, how loud each
+/// room is, and what to play.
+///
+/// ```toml
+/// [scenes.evening]
+/// groups = [["Living Room", "Kitchen"], ["Office"]]
+/// volumes = { "Living Room" = 25, Kitchen = 20 }
+/// favorite = "Jazz Radio"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scene {
+    /// Groups to form, by room name. The first room of each group is its coordinator.
+    pub groups: Vec<Vec<String>>,
+    /// Volume of each room, by name
+    pub volumes: BTreeMap<String, u16>,
+    /// Favorite to play on the first group, by name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub favorite: Option<String>,
+    /// URI to play on the first group instead, e.g. the URL of a radio stream
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+}
+
+impl Scene {
+    pub fn validate(&self, name: &str) -> Result<()> {
+        ensure!(
+            self.groups.iter().all(|g| !g.is_empty()),
+            "scene '{name}' has an empty group"
+        );
+        ensure!(
+            self.volumes.values().all(|v| *v <= 100),
+            "volumes of scene '{name}' must be between 0 and 100"
+        );
+        ensure!(
+            self.favorite.is_none() || self.uri.is_none(),
+            "scene '{name}' can't have both a favorite and a URI"
+        );
+        Ok(())
+    }
+}
+
+/// Add a scene to the end of the configuration file, creating the file if needed. A scene that is
+/// already in the file is never overwritten.
+pub fn save(path: &Path, name: &str, scene: &Scene) -> Result<()> {
+    let mut content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("Failed to read config file {}", path.display()));
+        }
+    };
+    let config: toml::Table = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+    ensure!(
+        config.get("scenes").and_then(|s| s.get(name)).is_none(),
+        "There is already a scene named '{name}'"
+    );
+
+    #[derive(Serialize)]
+    struct Section<'a> {
+        scenes: BTreeMap<&'a str, &'a Scene>,
+    }
+    let section = toml::to_string(&Section {
+        scenes: BTreeMap::from([(name, scene)]),
+    })?;
+    if !content.is_empty() {
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push('\n');
+    }
+    content.push_str(&section);
+    // e.g. if the scenes are written as an inline table
+    toml::from_str::<toml::Table>(&content)
+        .with_context(|| format!("Failed to add the scene to config file {}", path.display()))?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, content)
+        .with_context(|| format!("Failed to write config file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    use super::{Scene, ScenesConfig, save};
+
+    /// A config file of its own for each test, with the given content.
+    fn config_file(test: &str, content: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("sinuous-{}-{test}/config.toml", std::process::id()));
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn scenes(path: &PathBuf) -> ScenesConfig {
+        let mut config: toml::Table =
+            toml::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        config.remove("scenes").unwrap().try_into().unwrap()
+    }
+
+    fn scene(room: &str) -> Scene {
+        Scene {
+            groups: vec![vec![room.to_owned()]],
+            volumes: BTreeMap::from([(room.to_owned(), 20)]),
+            favorite: Some("Jazz Radio".to_owned()),
+            uri: None,
+        }
+    }
+
+    #[test]
+    fn save_next_to_other_scenes() {
+        let path = config_file(
+            "existing",
+            "volume_step = 2\n\n[scenes]\n\n[scenes.morning]\ngroups = [[\"Kitchen\"]]\n",
+        );
+        save(&path, "evening", &scene("Living Room")).unwrap();
+
+        let scenes = scenes(&path);
+        assert_eq!(scenes.keys().collect::<Vec<_>>(), ["evening", "morning"]);
+        assert_eq!(scenes["evening"].groups, [["Living Room"]]);
+        assert_eq!(scenes["evening"].volumes["Living Room"], 20);
+        assert_eq!(scenes["evening"].favorite.as_deref(), Some("Jazz Radio"));
+        assert_eq!(scenes["morning"].groups, [["Kitchen"]]);
+    }
+
+    #[test]
+    fn save_into_new_file() {
+        let path = config_file("new", "");
+        std::fs::remove_file(&path).unwrap();
+        save(&path, "evening", &scene("Office")).unwrap();
+        assert_eq!(scenes(&path)["evening"].groups, [["Office"]]);
+    }
+
+    #[test]
+    fn never_overwrite_a_scene() {
+        let content = "[scenes.evening]\ngroups = [[\"Kitchen\"]]\n";
+        let path = config_file("overwrite", content);
+        let err = save(&path, "evening", &scene("Office")).unwrap_err();
+        assert_eq!(err.to_string(), "There is already a scene named 'evening'");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+    }
+
+    #[test]
+    fn quoted_names() {
+        let path = config_file("quoted", "");
+        save(&path, "Late night", &scene("Office")).unwrap();
+        save(&path, "Mike's \"party\"", &scene("Kitchen")).unwrap();
+        save(&path, "café.bar", &scene("Bar")).unwrap();
+
+        let scenes = scenes(&path);
+        assert_eq!(scenes["Late night"].groups, [["Office"]]);
+        assert_eq!(scenes["Mike's \"party\""].groups, [["Kitchen"]]);
+        assert_eq!(scenes["café.bar"].groups, [["Bar"]]);
+    }
+
+    #[test]
+    fn inline_scenes_are_left_alone() {
+        let content = "scenes = { morning = { groups = [[\"Kitchen\"]] } }\n";
+        let path = config_file("inline", content);
+        assert!(save(&path, "evening", &scene("Office")).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+    }
+}
@@ -0,0 +1,275 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use sonor::Speaker;
+use tracing::{debug, warn};
+
+use crate::{
+    metrics::Metrics,
+    sonos::{AV_TRANSPORT, INSTANCE_ID, xml_escape},
+};
+
+/// Everything needed to put a group back the way it was after playing something else on it, like
+/// an announcement.
+#[derive(Debug)]
+pub struct Snapshot {
+    coordinator: String,
+    members: Vec<MemberState>,
+    playback: Playback,
+}
+
+#[derive(Debug)]
+struct MemberState {
+    uuid: String,
+    volume: u16,
+    muted: bool,
+    /// Highest volume the speaker can be set to, which may have changed since
+    max_volume: u16,
+}
+
+/// What a group is playing, and how far it got, to start it again later or on another group.
+#[derive(Debug)]
+pub struct Playback {
+    transport_uri: Option<String>,
+    metadata: String,
+    /// Position in the queue, starting at 1
+    track_no: Option<u32>,
+    /// Position in the track, in seconds
+    elapsed: u32,
+    /// e.g. NORMAL or SHUFFLE_NOREPEAT
+    play_mode: String,
+    playing: bool,
+}
+
+impl Snapshot {
+    /// Record the state of the group made of `members`, the first of which is its coordinator.
+    /// `max_volume` gives the highest volume of each speaker by UUID, which restoring sticks to.
+    pub async fn take(
+        metrics: &Metrics,
+        speakers: &BTreeMap<String, Speaker>,
+        members: &[String],
+        max_volume: impl Fn(&str) -> u16,
+    ) -> Result<Self> {
+        let coordinator_uuid = members.first().context("Empty group")?;
+        let coordinator = speaker(speakers, coordinator_uuid)?;
+        let playback = Playback::take(metrics, coordinator).await?;
+
+        let mut member_states = vec![];
+        for uuid in members {
+            let speaker = speaker(speakers, uuid)?;
+            member_states.push(MemberState {
+                uuid: uuid.clone(),
+                volume: metrics.track("GetVolume", speaker.volume()).await?,
+                muted: metrics.track("GetMute", speaker.mute()).await?,
+                max_volume: max_volume(uuid),
+            });
+        }
+
+        let snapshot = Self {
+            coordinator: coordinator_uuid.clone(),
+            members: member_states,
+            playback,
+        };
+        debug!(?snapshot, "Took snapshot");
+        Ok(snapshot)
+    }
+
+    /// Put the group back the way it was: members, volumes, source, position and play mode.
+    pub async fn restore(
+        &self,
+        metrics: &Metrics,
+        speakers: &BTreeMap<String, Speaker>,
+    ) -> Result<()> {
+        let coordinator = speaker(speakers, &self.coordinator)?;
+
+        // Bring back the speakers that left the group in the meantime, and send away the ones that
+        // joined it
+        let topology = metrics
+            .track("GetZoneGroupState", coordinator.zone_group_state())
+            .await?;
+        let current_members: Vec<&str> = topology
+            .get(&self.coordinator)
+            .into_iter()
+            .flatten()
+            .map(|s| s.uuid())
+            .collect();
+        for member in self.members.iter().skip(1) {
+            if !current_members.contains(&member.uuid.as_str()) {
+                metrics
+                    .track(
+                        "SetAVTransportURI",
+                        speaker(speakers, &member.uuid)?
+                            .set_transport_uri(&format!("x-rincon:{}", self.coordinator), ""),
+                    )
+                    .await
+                    .with_context(|| {
+                        format!("Failed to bring {} back in the group", member.uuid)
+                    })?;
+            }
+        }
+        for uuid in current_members {
+            if !self.members.iter().any(|m| m.uuid == uuid) {
+                metrics
+                    .track(
+                        "BecomeCoordinatorOfStandaloneGroup",
+                        speaker(speakers, uuid)?.leave(),
+                    )
+                    .await
+                    .with_context(|| format!("Failed to take {uuid} out of the group"))?;
+            }
+        }
+
+        // Restore the volumes before anything plays again
+        for member in &self.members {
+            let speaker = speaker(speakers, &member.uuid)?;
+            metrics
+                .track(
+                    "SetVolume",
+                    speaker.set_volume(member.volume.min(member.max_volume)),
+                )
+                .await?;
+            metrics
+                .track("SetMute", speaker.set_mute(member.muted))
+                .await?;
+        }
+
+        self.playback
+            .resume(metrics, coordinator, &self.coordinator)
+            .await
+    }
+}
+
+impl Playback {
+    /// Record what the group of `coordinator` is playing.
+    pub async fn take(metrics: &Metrics, coordinator: &Speaker) -> Result<Self> {
+        let mut media_info = metrics
+            .track(
+                "GetMediaInfo",
+                coordinator.action(AV_TRANSPORT, "GetMediaInfo", INSTANCE_ID),
+            )
+            .await?;
+        let track = metrics
+            .track("GetPositionInfo", coordinator.track())
+            .await?;
+        let mut settings = metrics
+            .track(
+                "GetTransportSettings",
+                coordinator.action(AV_TRANSPORT, "GetTransportSettings", INSTANCE_ID),
+            )
+            .await?;
+        let state = transport_state(metrics, coordinator).await?;
+
+        Ok(Self {
+            transport_uri: media_info.remove("CurrentURI").filter(|u| !u.is_empty()),
+            metadata: media_info.remove("CurrentURIMetaData").unwrap_or_default(),
+            track_no: track.as_ref().map(|t| t.track_no()),
+            elapsed: track.as_ref().map_or(0, |t| t.elapsed()),
+            play_mode: settings
+                .remove("PlayMode")
+                .unwrap_or_else(|| "NORMAL".to_owned()),
+            playing: state == "PLAYING" || state == "TRANSITIONING",
+        })
+    }
+
+    /// Whether the group was playing from its queue, rather than e.g. a radio or an input.
+    pub fn is_queue(&self) -> bool {
+        self.transport_uri
+            .as_deref()
+            .is_some_and(|uri| uri.starts_with("x-rincon-queue:"))
+    }
+
+    /// Play the same thing on the group of `coordinator` (whose UUID is `uuid`), from the same
+    /// point and with the same play mode. A queue is played from that group's own queue.
+    pub async fn resume(&self, metrics: &Metrics, coordinator: &Speaker, uuid: &str) -> Result<()> {
+        if let Some(uri) = &self.transport_uri {
+            let (uri, metadata) = if self.is_queue() {
+                (format!("x-rincon-queue:{uuid}#0"), String::new())
+            } else {
+                (xml_escape(uri), xml_escape(&self.metadata))
+            };
+            metrics
+                .track(
+                    "SetAVTransportURI",
+                    coordinator.set_transport_uri(&uri, &metadata),
+                )
+                .await?;
+            // Only the queue can be moved around in, not radios or inputs
+            if self.is_queue() {
+                if let Some(track_no) = self.track_no {
+                    metrics
+                        .track("Seek", coordinator.seek_track(track_no))
+                        .await?;
+                }
+                if self.elapsed > 0
+                    && let Err(err) = metrics
+                        .track("Seek", coordinator.skip_to(self.elapsed))
+                        .await
+                {
+                    warn!(%err, "Failed to restore the position in the track");
+                }
+            }
+        }
+
+        let payload = format!("{INSTANCE_ID}<NewPlayMode>{}</NewPlayMode>", self.play_mode);
+        if let Err(err) = metrics
+            .track(
+                "SetPlayMode",
+                coordinator.action(AV_TRANSPORT, "SetPlayMode", &payload),
+            )
+            .await
+        {
+            // Some sources don't support shuffle or repeat
+            warn!(%err, "Failed to restore the play mode");
+        }
+
+        if self.playing {
+            metrics.track("Play", coordinator.play()).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Wait for the coordinator to be done playing what it was just asked to play, for at most
+/// `limit`.
+pub async fn wait_until_stopped(
+    metrics: &Metrics,
+    coordinator: &Speaker,
+    limit: Duration,
+) -> Result<()> {
+    let start = Instant::now();
+    let mut started = false;
+    loop {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let state = transport_state(metrics, coordinator).await?;
+        match state.as_str() {
+            "PLAYING" | "TRANSITIONING" => started = true,
+            // It may take a moment for the speaker to start
+            _ if started || start.elapsed() > Duration::from_secs(5) => return Ok(()),
+            _ => {}
+        }
+        if start.elapsed() > limit {
+            warn!("Gave up waiting for the announcement to finish");
+            return Ok(());
+        }
+    }
+}
+
+async fn transport_state(metrics: &Metrics, speaker: &Speaker) -> Result<String> {
+    metrics
+        .track(
+            "GetTransportInfo",
+            speaker.action(AV_TRANSPORT, "GetTransportInfo", INSTANCE_ID),
+        )
+        .await?
+        .remove("CurrentTransportState")
+        .context("No CurrentTransportState in GetTransportInfo response")
+}
+
+fn speaker<'a>(speakers: &'a BTreeMap<String, Speaker>, uuid: &str) -> Result<&'a Speaker> {
+    speakers
+        .get(uuid)
+        .with_context(|| format!("Unknown speaker {uuid}"))
+}
@@ -1,18 +1,48 @@
 use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{Context, Result};
-use futures::TryStreamExt;
+use futures::{TryStreamExt, future::join_all};
+use serde_derive::Serialize;
 use sonor::{Speaker, SpeakerInfo, Track, TrackInfo, URN};
 use std::net::Ipv4Addr;
 use tokio::{
     select,
-    sync::mpsc::{Receiver, Sender},
+    sync::{
+        mpsc::{Sender, UnboundedReceiver},
+        watch,
+    },
+    task::JoinHandle,
 };
 use tracing::{debug, error, info, warn};
 
-use crate::{Action, Direction, Update, ViewMode};
+use crate::{
+    Action, Notification, Request, Update, ViewMode,
+    alarms::{self, Alarm},
+    config::Config,
+    fade::{Fade, Level},
+    file_server::{self, FileServer},
+    media,
+    metrics::Metrics,
+    scene::{self, Scene, ScenesConfig},
+    snapshot::{self, Playback, Snapshot},
+};
+
+pub(crate) const AV_TRANSPORT: &URN = &URN::service("schemas-upnp-org", "AVTransport", 1);
+const GROUP_RENDERING_CONTROL: &URN = &URN::service("schemas-upnp-org", "GroupRenderingControl", 1);
+const AUDIO_IN: &URN = &URN::service("schemas-upnp-org", "AudioIn", 1);
+const HT_CONTROL: &URN = &URN::service("schemas-upnp-org", "HTControl", 1);
+pub(crate) const INSTANCE_ID: &str = "<InstanceID>0</InstanceID>";
+/// How often the playing indicators of all the groups are refreshed
+const INDICATOR_INTERVAL: Duration = Duration::from_secs(5);
+/// How often the alarms are listed again while they're displayed
+const ALARMS_INTERVAL: Duration = Duration::from_secs(30);
+/// How often the speakers are asked whether they still answer
+const REACHABILITY_INTERVAL: Duration = Duration::from_secs(15);
+/// Longest an announcement can play before playback is resumed anyway
+const ANNOUNCEMENT_TIMEOUT: Duration = Duration::from_secs(300);
 
 #[derive(Debug, Clone)]
 pub struct FavoritePlaylist {
@@ -22,17 +52,76 @@ pub struct FavoritePlaylist {
     pub metadata: String,
 }
 
+/// What a radio station is playing, which doesn't fit in the usual artist / album / title.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RadioInfo {
+    pub station: String,
+    /// Usually "artist - title"
+    pub song: Option<String>,
+    pub show: Option<String>,
+}
+
+/// Line-in or TV input of a speaker, which any group can play.
+#[derive(Debug, Clone)]
+pub struct AudioInput {
+    /// e.g. "Living Room (TV)"
+    pub name: String,
+    pub uri: String,
+}
+
+/// A single speaker, e.g. to pick where an alarm plays.
+#[derive(Debug, Clone)]
+pub struct Room {
+    pub uuid: String,
+    pub name: String,
+    /// Index of the group it belongs to
+    pub group: usize,
+}
+
 #[derive(Debug)]
 pub struct SpeakerState {
     pub is_playing: bool,
     pub current_volume: u16,
     pub group_names: Vec<String>,
+    /// Status of each group, in the same order as their names, if it's been fetched
+    pub group_statuses: Vec<Option<GroupStatus>>,
+    /// Whether each group is playing and muted, in the same order as their names
+    pub group_indicators: Vec<Option<GroupIndicator>>,
     pub selected_group: usize,
     pub now_playing: Option<Arc<TrackInfo>>,
+    /// URL of the album art of the current track
+    pub album_art: Option<String>,
     pub queue: Arc<Vec<Track>>,
+    /// Selected track in the queue, if the user moved away from the one playing
+    pub selected_queue_item: Option<usize>,
     pub current_view: ViewMode,
     pub favorites: Vec<FavoritePlaylist>,
     pub selected_favorite: usize,
+    /// All the speakers, group by group, with the coordinator of each group first
+    pub rooms: Vec<Room>,
+    pub alarms: Vec<Alarm>,
+    pub selected_alarm: usize,
+    /// Inputs of all the speakers of the household
+    pub inputs: Vec<AudioInput>,
+    /// Name of the input the selected group is playing, if any
+    pub playing_input: Option<String>,
+    /// Set when the selected group is playing a radio station
+    pub radio: Option<RadioInfo>,
+    /// Names of the scenes that can be applied, including the ones saved since starting
+    pub scenes: Vec<String>,
+    /// Number of the last request from the UI that was handled: this state reflects it, and all
+    /// the ones before
+    pub handled: u64,
+    /// Changes shown before the speakers confirmed them, set by the UI
+    pub pending: PendingChanges,
+}
+
+/// Which parts of the state are still waiting for the speakers to confirm a change.
+#[derive(Debug, Default)]
+pub struct PendingChanges {
+    pub playing: bool,
+    pub volume: bool,
+    pub favorite: bool,
 }
 
 impl SpeakerState {
@@ -41,40 +130,175 @@ impl SpeakerState {
     }
 }
 
+/// Why we couldn't connect to the speakers.
+#[derive(Debug)]
+pub enum ConnectError {
+    /// Discovery didn't find any speaker on the network
+    NoSpeakersFound,
+    /// None of the speakers given on the command line could be found
+    NotFound(Vec<String>),
+    /// Something went wrong while talking to the speakers
+    Network(String),
+}
+
+impl std::fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectError::NoSpeakersFound => write!(
+                f,
+                "No Sonos speakers were found. Make sure they are switched on and on the same network as this computer."
+            ),
+            ConnectError::NotFound(devices) => write!(
+                f,
+                "Could not find or connect to {}. Check the names or addresses given with --device.",
+                devices.join(", ")
+            ),
+            ConnectError::Network(err) => {
+                write!(f, "Network error while talking to the speakers: {err}")
+            }
+        }
+    }
+}
+
+impl From<sonor::Error> for ConnectError {
+    fn from(err: sonor::Error) -> Self {
+        ConnectError::Network(err.to_string())
+    }
+}
+
+/// Whether a group is playing, as shown next to its name in the tab bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackState {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+/// Cheap to fetch status of a group, refreshed for all the groups every few seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupIndicator {
+    pub state: PlaybackState,
+    pub muted: bool,
+}
+
+/// Playback state of a single group, as published to external integrations (e.g. MQTT).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GroupStatus {
+    pub name: String,
+    pub coordinator: String,
+    pub playing: bool,
+    pub volume: u16,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub elapsed: u32,
+    pub duration: u32,
+    pub queue_length: usize,
+}
+
 pub struct SonosService {
     update_tx: Sender<Update>,
-    cmd_rx: Receiver<Action>,
+    cmd_rx: UnboundedReceiver<Request>,
+    status_tx: watch::Sender<Vec<GroupStatus>>,
+    metrics: Arc<Metrics>,
     speakers_by_uuid: BTreeMap<String, Speaker>,
     groups: Vec<SpeakerGroup>,
     selected_group: usize,
+    selected_queue_item: Option<usize>,
     current_view: ViewMode,
     favorites: Vec<FavoritePlaylist>,
     selected_favorite: usize,
+    alarms: Vec<Alarm>,
+    selected_alarm: usize,
+    inputs: Vec<AudioInput>,
+    /// Serves local files to the speakers, started the first time some are played
+    file_server: Option<FileServer>,
+    /// Volume ramps of the last play or pause, which may still be running, by coordinator
+    fades: BTreeMap<String, Fade>,
+    /// Announcements waiting for their clip to be over, by coordinator
+    announcements: BTreeMap<String, JoinHandle<()>>,
+    /// Number of the last request from the UI that was handled, once the state shows it
+    handled: u64,
+    // Settings
+    scenes: ScenesConfig,
+    /// Where new scenes are saved
+    config_path: Option<PathBuf>,
+    /// By room name
+    max_volume: BTreeMap<String, u16>,
+    fade_duration: Duration,
+    initial_group: Option<String>,
+    poll_interval: Duration,
+    discovery_timeout: Duration,
     // Cached state
     cached_is_playing: bool,
     cached_volume: u16,
     cached_now_playing: Option<Arc<TrackInfo>>,
+    cached_album_art: Option<String>,
+    /// What the selected group is playing, to find out whether it's one of the inputs or a radio
+    cached_transport_uri: Option<String>,
+    /// Metadata of the transport URI, which has the name of the station for radios
+    cached_media_metadata: Option<String>,
     cached_queue: Arc<Vec<Track>>,
+    /// By coordinator
+    cached_indicators: BTreeMap<String, GroupIndicator>,
 }
 
 impl SonosService {
-    pub fn new(update_tx: Sender<Update>, cmd_rx: Receiver<Action>) -> Self {
+    pub fn new(
+        update_tx: Sender<Update>,
+        cmd_rx: UnboundedReceiver<Request>,
+        config: &Config,
+    ) -> Self {
+        let (status_tx, _) = watch::channel(vec![]);
         Self {
             update_tx,
             cmd_rx,
+            status_tx,
+            metrics: Arc::default(),
             speakers_by_uuid: BTreeMap::new(),
             groups: vec![],
             selected_group: 0,
-            current_view: ViewMode::Queue,
+            selected_queue_item: None,
+            current_view: config.view,
             favorites: vec![],
             selected_favorite: 0,
+            alarms: vec![],
+            selected_alarm: 0,
+            inputs: vec![],
+            file_server: None,
+            fades: BTreeMap::new(),
+            announcements: BTreeMap::new(),
+            handled: 0,
+            scenes: config.scenes.clone(),
+            config_path: config.path.clone(),
+            max_volume: config.max_volume.clone(),
+            fade_duration: config.fade(),
+            initial_group: config.group.clone(),
+            poll_interval: config.poll_interval(),
+            discovery_timeout: config.discovery_timeout(),
             cached_is_playing: false,
             cached_volume: 0,
             cached_now_playing: None,
+            cached_album_art: None,
+            cached_transport_uri: None,
+            cached_media_metadata: None,
+            cached_indicators: BTreeMap::new(),
             cached_queue: Arc::new(vec![]),
         }
     }
 
+    /// Subscribe to the status of all the groups.
+    ///
+    /// The statuses of the other groups are only refreshed as long as there is at least one
+    /// subscriber.
+    pub fn subscribe_groups(&self) -> watch::Receiver<Vec<GroupStatus>> {
+        self.status_tx.subscribe()
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
     pub fn start(self, provided_devices: (Vec<Ipv4Addr>, Vec<String>)) {
         tokio::spawn(async move {
             if let Err(err) = self.inner_loop(provided_devices).await {
@@ -84,7 +308,120 @@ impl SonosService {
     }
 
     async fn inner_loop(mut self, provided_devices: (Vec<Ipv4Addr>, Vec<String>)) -> Result<()> {
-        let speakers = get_speakers(provided_devices).await?;
+        // Keep trying to connect until it works, or until the UI goes away
+        while let Err(err) = self.connect(&provided_devices).await {
+            warn!(%err, "Failed to connect to the speakers");
+            if self.update_tx.send(Update::Failed(err)).await.is_err() {
+                return Ok(());
+            }
+            // Wait for the user to ask for another attempt
+            loop {
+                match self.cmd_rx.recv().await {
+                    Some(Request {
+                        action: Action::Retry,
+                        ..
+                    }) => break,
+                    Some(_) => {}
+                    None => return Ok(()),
+                }
+            }
+        }
+
+        // Initial state fetch
+        if let Err(e) = self.refresh_state().await {
+            warn!("Failed to fetch initial state: {}", e);
+        }
+
+        let mut ticker = tokio::time::interval(self.poll_interval);
+        let mut indicator_ticker = tokio::time::interval(INDICATOR_INTERVAL);
+        let mut alarms_ticker = tokio::time::interval(ALARMS_INTERVAL);
+        let mut reachability_ticker = tokio::time::interval(REACHABILITY_INTERVAL);
+        debug!("Starting sonos loop");
+
+        loop {
+            select! {
+                _tick = ticker.tick() => {
+                    // time to refresh our state
+                    if let Err(e) = self.refresh_state().await {
+                        warn!("Failed to refresh state: {}", e);
+                    }
+                    if self.wants_group_statuses() {
+                        self.refresh_group_statuses().await;
+                    }
+                    self.send_update().await;
+                }
+                _tick = indicator_ticker.tick() => {
+                    self.refresh_indicators().await;
+                    self.send_update().await;
+                }
+                // Alarms are only changed by commands, unless some other app edits them
+                _tick = alarms_ticker.tick(), if self.current_view == ViewMode::Alarms => {
+                    if let Err(e) = self.refresh_alarms().await {
+                        warn!("Failed to refresh alarms: {}", e);
+                    }
+                    self.send_update().await;
+                }
+                _tick = reachability_ticker.tick(), if !self.status_tx.is_closed() => {
+                    self.check_reachability();
+                }
+                cmd = self.cmd_rx.recv() => {
+                    if let Some(c) = cmd {
+                        // Take all the commands that piled up, and merge what can be
+                        let mut requests = vec![c];
+                        while let Ok(c) = self.cmd_rx.try_recv() {
+                            requests.push(c);
+                        }
+                        let handled = requests.iter().filter_map(|r| r.seq).max();
+                        let cmds = requests.into_iter().map(|r| r.action).collect();
+
+                        let selected_group = self.selected_group;
+                        let mut needs_refresh = false;
+                        for c in coalesce(cmds) {
+                            match self.handle_command(c).await {
+                                Ok(r) => if r { needs_refresh = true; },
+                                Err(e) => {
+                                    warn!("Error handling command: {}", e);
+                                    self.notify_error(&e).await;
+                                }
+                            }
+                        }
+                        // Unless the state is stale, it shows the effect of the commands
+                        let mut refreshed = true;
+                        if needs_refresh {
+                            if let Err(e) = self.refresh_state().await {
+                                warn!("Failed to refresh state after commands: {}", e);
+                                refreshed = false;
+                            }
+                            if self.current_view == ViewMode::Overview {
+                                self.refresh_group_statuses().await;
+                            }
+                        } else if self.selected_group != selected_group {
+                            // Only the newly selected group needs fetching
+                            if let Err(e) = self.refresh_state().await {
+                                warn!("Failed to refresh state after commands: {}", e);
+                                refreshed = false;
+                            }
+                        }
+                        if let Some(seq) = handled.filter(|_| refreshed) {
+                            self.handled = seq;
+                        }
+                    } else {
+                        warn!("Command channel was closed: exiting...");
+                        break;
+                    }
+                    self.send_update().await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the speakers, and fetch the groups and favorites.
+    async fn connect(
+        &mut self,
+        provided_devices: &(Vec<Ipv4Addr>, Vec<String>),
+    ) -> Result<(), ConnectError> {
+        let speakers = self.discover_speakers(provided_devices).await?;
 
         let mut speakers_by_uuid = BTreeMap::new();
         // TODO do in parallel?
@@ -97,8 +434,11 @@ impl SonosService {
         let (_uuid, speaker) = speakers_by_uuid
             .iter()
             .next()
-            .context("No speaker discovered!")?;
-        let groups = speaker.zone_group_state().await?;
+            .ok_or(ConnectError::NoSpeakersFound)?;
+        let groups = self
+            .metrics
+            .track("GetZoneGroupState", speaker.zone_group_state())
+            .await?;
         debug!("Found {} groups", groups.len());
 
         // Fetch favorites from the speaker (before moving speakers_by_uuid)
@@ -119,115 +459,267 @@ impl SonosService {
             .collect::<Vec<_>>();
         self.groups = group_list;
         self.speakers_by_uuid = speakers_by_uuid;
+        self.inputs = self.find_inputs();
+        debug!("Found {} inputs", self.inputs.len());
 
-        // Initial state fetch
-        if let Err(e) = self.refresh_state().await {
-            warn!("Failed to fetch initial state: {}", e);
+        if let Some(name) = &self.initial_group {
+            match self.groups.iter().position(|g| g.matches(name)) {
+                Some(index) => self.selected_group = index,
+                None => warn!("No group named {} was found", name),
+            }
         }
 
-        let mut ticker = tokio::time::interval(Duration::from_secs(1));
-        debug!("Starting sonos loop");
-
-        loop {
-            select! {
-                _tick = ticker.tick() => {
-                    // time to refresh our state
-                    if let Err(e) = self.refresh_state().await {
-                        warn!("Failed to refresh state: {}", e);
-                    }
-                    self.send_update().await;
-                }
-                cmd = self.cmd_rx.recv() => {
-                    if let Some(c) = cmd {
-                        let mut needs_refresh = false;
-
-                        // Process the first command
-                        match self.handle_command(c).await {
-                            Ok(r) => if r { needs_refresh = true; },
-                            Err(e) => warn!("Error handling command: {}", e),
-                        }
+        // A misspelt room would silently go without its limit
+        for room in self.max_volume.keys() {
+            let known = self
+                .groups
+                .iter()
+                .flat_map(|g| &g.speakers)
+                .any(|s| s.name().eq_ignore_ascii_case(room));
+            if !known {
+                warn!("No room named {} was found for max_volume", room);
+                self.notify(Notification::error(format!(
+                    "max_volume has no effect on '{room}': there is no room with that name"
+                )))
+                .await;
+            }
+        }
+        Ok(())
+    }
 
-                        // Drain pending commands
-                        while let Ok(c) = self.cmd_rx.try_recv() {
-                            match self.handle_command(c).await {
-                                Ok(r) => if r { needs_refresh = true; },
-                                Err(e) => warn!("Error handling batched command: {}", e),
-                            }
-                        }
+    async fn discover_speakers(
+        &self,
+        provided_devices: &(Vec<Ipv4Addr>, Vec<String>),
+    ) -> Result<Vec<Speaker>, ConnectError> {
+        let (provided_ips, provided_names) = provided_devices;
+        let mut speakers: Vec<Speaker> = vec![];
+        let mut missing = vec![];
+
+        debug!("Connecting to provided speakers...");
+        for e in provided_ips {
+            if let Some(spk) = sonor::Speaker::from_ip(*e).await.unwrap_or(None) {
+                self.notify_discovered(&spk).await;
+                speakers.push(spk);
+            } else {
+                debug!("Not connecting to {e} due to errors");
+                missing.push(e.to_string());
+            }
+        }
+        for e in provided_names {
+            if let Some(device) = sonor::find(e, self.discovery_timeout).await? {
+                self.notify_discovered(&device).await;
+                speakers.push(device);
+            } else {
+                debug!("Not connecting to {e} due to errors");
+                missing.push(e.clone());
+            }
+        }
+        if provided_ips.is_empty() && provided_names.is_empty() {
+            debug!("Discovering speakers...");
+            let mut devices = sonor::discover(self.discovery_timeout).await?;
+            while let Some(device) = devices.try_next().await? {
+                self.notify_discovered(&device).await;
+                speakers.push(device);
+            }
+        }
 
-                        if needs_refresh && let Err(e) = self.refresh_state().await {
-                            warn!("Failed to refresh state after commands: {}", e);
-                        }
-                    } else {
-                        warn!("Command channel was closed: exiting...");
-                        break;
-                    }
-                    self.send_update().await;
-                }
+        info!("Found {} speakers", speakers.len());
+        match (speakers.is_empty(), missing.is_empty()) {
+            (true, true) => Err(ConnectError::NoSpeakersFound),
+            (true, false) => Err(ConnectError::NotFound(missing)),
+            (false, false) => {
+                warn!("Could not connect to {}", missing.join(", "));
+                Ok(speakers)
             }
+            (false, true) => Ok(speakers),
         }
-        Ok(())
+    }
+
+    /// Let the UI know about a speaker as soon as it is found.
+    async fn notify_discovered(&self, speaker: &Speaker) {
+        let name = match speaker.name().await {
+            Ok(name) => name,
+            Err(_) => speaker.device().friendly_name().to_string(),
+        };
+        let _ = self.update_tx.send(Update::Discovered(name)).await;
     }
 
     async fn handle_command(&mut self, cmd: Action) -> Result<bool> {
         debug!(?cmd, "Handling command");
+        let fades: Vec<Fade> = match &cmd {
+            // Groups are rearranged, which may involve any of them
+            Action::TransferPlayback { .. } | Action::ApplyScene(_) => {
+                std::mem::take(&mut self.fades).into_values().collect()
+            }
+            cmd if cancels_fade(cmd) => self
+                .groups
+                .get(self.selected_group)
+                .and_then(|group| self.fades.remove(&group.coordinator))
+                .into_iter()
+                .collect(),
+            _ => vec![],
+        };
+        for fade in fades {
+            fade.cancel().await;
+        }
         match cmd {
             // Playback controls
             Action::Play => {
                 let speaker = self.current_speaker().context("No selected group")?;
-                speaker.play().await?;
+                if self.fade_duration.is_zero() || self.cached_is_playing {
+                    self.metrics.track("Play", speaker.play()).await?;
+                } else {
+                    // Start silently, then bring the volume up
+                    let levels = self.group_levels().await?;
+                    for level in &levels {
+                        self.metrics
+                            .track("SetVolume", level.speaker.set_volume(0))
+                            .await?;
+                    }
+                    self.metrics.track("Play", speaker.play()).await?;
+                    let (uuid, _) = self.current_coordinator()?;
+                    let fade = Fade::fade_in(self.metrics.clone(), levels, self.fade_duration);
+                    self.fades.insert(uuid.to_owned(), fade);
+                }
                 Ok(true)
             }
             Action::Pause => {
                 let speaker = self.current_speaker().context("No selected group")?;
-                speaker.pause().await?;
+                if self.fade_duration.is_zero() || !self.cached_is_playing {
+                    self.metrics.track("Pause", speaker.pause()).await?;
+                } else {
+                    let speaker = speaker.clone();
+                    let levels = self.group_levels().await?;
+                    let (uuid, _) = self.current_coordinator()?;
+                    let fade =
+                        Fade::fade_out(self.metrics.clone(), speaker, levels, self.fade_duration);
+                    self.fades.insert(uuid.to_owned(), fade);
+                }
                 Ok(true)
             }
             Action::Next => {
                 let speaker = self.current_speaker().context("No selected group")?;
-                speaker.next().await?;
+                self.metrics.track("Next", speaker.next()).await?;
                 Ok(true)
             }
             Action::Prev => {
                 let speaker = self.current_speaker().context("No selected group")?;
-                speaker.previous().await?;
+                self.metrics.track("Previous", speaker.previous()).await?;
                 Ok(true)
             }
             Action::VolAdjust(v) => {
+                let (uuid, speaker) = self.current_coordinator()?;
+                let max = self.max_volume_of(uuid);
+                // Not relative, so that the volume never goes over the limit, even briefly
+                let volume = self.metrics.track("GetVolume", speaker.volume()).await?;
+                let volume = (i32::from(volume) + i32::from(v)).clamp(0, i32::from(max)) as u16;
+                self.metrics
+                    .track("SetVolume", speaker.set_volume(volume))
+                    .await?;
+                Ok(true)
+            }
+            Action::SetVolume(v) => {
+                let (uuid, speaker) = self.current_coordinator()?;
+                let volume = v.min(self.max_volume_of(uuid));
+                self.metrics
+                    .track("SetVolume", speaker.set_volume(volume))
+                    .await?;
+                Ok(true)
+            }
+            Action::Seek(position) => {
                 let speaker = self.current_speaker().context("No selected group")?;
-                speaker.set_volume_relative(v).await.map(drop)?;
+                self.metrics
+                    .track("Seek", speaker.skip_to(position))
+                    .await?;
                 Ok(true)
             }
 
             // Group switching
-            Action::NextSpeaker => {
-                self.select_next_group();
-                Ok::<bool, anyhow::Error>(true)
+            // The newly selected group is fetched once all the commands are handled
+            Action::CycleGroup(offset) => {
+                let count = self.groups.len().max(1) as isize;
+                self.selected_group =
+                    (self.selected_group as isize + offset).rem_euclid(count) as usize;
+                self.selected_queue_item = None;
+                Ok::<bool, anyhow::Error>(false)
             }
-            Action::PrevSpeaker => {
-                self.select_prev_group();
-                Ok::<bool, anyhow::Error>(true)
+            Action::SelectGroup(index) => {
+                if index < self.groups.len() && index != self.selected_group {
+                    self.selected_group = index;
+                    self.selected_queue_item = None;
+                }
+                Ok(false)
             }
 
             // View switching
             Action::SwitchView(view_mode) => {
                 self.current_view = view_mode;
+                if view_mode == ViewMode::Alarms {
+                    self.refresh_alarms().await?;
+                }
+                // The overview needs the status of all the groups straight away
+                Ok(view_mode == ViewMode::Overview)
+            }
+
+            // Queue navigation
+            Action::NavigateQueue(offset) => {
+                let current = self
+                    .selected_queue_item
+                    .or_else(|| self.playing_queue_item())
+                    .unwrap_or(0);
+                let last = self.cached_queue.len().saturating_sub(1);
+                self.selected_queue_item = Some(current.saturating_add_signed(offset).min(last));
                 Ok(false)
             }
+            Action::SelectQueueItem(index) => {
+                if index < self.cached_queue.len() {
+                    self.selected_queue_item = Some(index);
+                }
+                Ok(false)
+            }
+            Action::PlayQueueItem(index) => {
+                let group = self
+                    .groups
+                    .get(self.selected_group)
+                    .context("No selected group")?;
+                let speaker = self.current_speaker().context("No selected group")?;
+                let track = self
+                    .cached_queue
+                    .get(index)
+                    .context("No such track in the queue")?;
+                // The group may be playing something else than its queue (e.g. a radio)
+                self.metrics
+                    .track(
+                        "SetAVTransportURI",
+                        speaker.set_transport_uri(
+                            &format!("x-rincon-queue:{}#0", group.coordinator),
+                            "",
+                        ),
+                    )
+                    .await?;
+                self.metrics
+                    .track("Seek", speaker.seek_track(index as u32 + 1))
+                    .await?;
+                self.metrics.track("Play", speaker.play()).await?;
+                self.notify(Notification::info(format!("Playing: {}", track.title())))
+                    .await;
+                self.selected_queue_item = None;
+                Ok(true)
+            }
 
             // Favorites navigation
-            Action::NavigateFavorites(direction) => {
-                match direction {
-                    Direction::Up => {
-                        if self.selected_favorite > 0 {
-                            self.selected_favorite -= 1;
-                        }
-                    }
-                    Direction::Down => {
-                        if self.selected_favorite < self.favorites.len().saturating_sub(1) {
-                            self.selected_favorite += 1;
-                        }
-                    }
+            Action::NavigateFavorites(offset) => {
+                let last = self.favorites.len().saturating_sub(1);
+                self.selected_favorite = self
+                    .selected_favorite
+                    .saturating_add_signed(offset)
+                    .min(last);
+                Ok(false)
+            }
+
+            Action::SelectFavorite(index) => {
+                if index < self.favorites.len() {
+                    self.selected_favorite = index;
                 }
                 Ok(false)
             }
@@ -242,7 +734,11 @@ impl SonosService {
 
                     // Clear the queue first
                     debug!("Clearing queue...");
-                    if let Err(e) = speaker.clear_queue().await {
+                    if let Err(e) = self
+                        .metrics
+                        .track("RemoveAllTracksFromQueue", speaker.clear_queue())
+                        .await
+                    {
                         warn!("Failed to clear queue: {}", e);
                     }
 
@@ -255,23 +751,25 @@ impl SonosService {
                     // For containers (playlists), use AddURIToQueue
                     if unescaped_uri.starts_with("x-rincon-cpcontainer:") {
                         debug!("Using AddURIToQueue for container...");
-                        let service = URN::service("schemas-upnp-org", "AVTransport", 1);
-                        let payload = format!(
-                            r#"<InstanceID>0</InstanceID>
-<EnqueuedURI>{}</EnqueuedURI>
-<EnqueuedURIMetaData>{}</EnqueuedURIMetaData>
-<DesiredFirstTrackNumberEnqueued>0</DesiredFirstTrackNumberEnqueued>
-<EnqueueAsNext>1</EnqueueAsNext>"#,
-                            favorite.uri, favorite.metadata
-                        );
-
-                        match speaker.action(&service, "AddURIToQueue", &payload).await {
+                        match self
+                            .metrics
+                            .track(
+                                "AddURIToQueue",
+                                add_uri_to_queue(speaker, &favorite.uri, &favorite.metadata, true),
+                            )
+                            .await
+                        {
                             Ok(_) => {
                                 debug!("AddURIToQueue succeeded");
                                 // Start playback
                                 debug!("Starting playback...");
-                                speaker.play().await?;
+                                self.metrics.track("Play", speaker.play()).await?;
                                 info!("Successfully started playing: {}", favorite.title);
+                                self.notify(Notification::info(format!(
+                                    "Playing: {}",
+                                    favorite.title
+                                )))
+                                .await;
                             }
                             Err(e) => {
                                 error!("AddURIToQueue failed: {:?}", e);
@@ -281,11 +779,16 @@ impl SonosService {
                     } else {
                         // For individual tracks, use queue_next
                         debug!("Using queue_next for track...");
-                        speaker
-                            .queue_next(&unescaped_uri, &unescaped_metadata)
+                        self.metrics
+                            .track(
+                                "AddURIToQueue",
+                                speaker.queue_next(&unescaped_uri, &unescaped_metadata),
+                            )
                             .await?;
-                        speaker.next().await?;
+                        self.metrics.track("Next", speaker.next()).await?;
                         info!("Successfully started playing: {}", favorite.title);
+                        self.notify(Notification::info(format!("Playing: {}", favorite.title)))
+                            .await;
                     }
 
                     Ok(true)
@@ -295,11 +798,657 @@ impl SonosService {
                 }
             }
 
-            Action::Nop => Ok(false),
+            // Arbitrary URLs
+            Action::PlayUrl { url, enqueue } => {
+                let item = media::url_item(&url).await?;
+                let speaker = self.current_speaker().context("No selected group")?;
+                let (uri, metadata) = (xml_escape(&item.uri), xml_escape(&item.metadata));
+                if enqueue {
+                    anyhow::ensure!(!item.is_stream, "Streams can't be added to the queue");
+                    self.metrics
+                        .track("AddURIToQueue", speaker.queue_end(&uri, &metadata))
+                        .await?;
+                    self.notify(Notification::info(format!(
+                        "Added to the queue: {}",
+                        item.title
+                    )))
+                    .await;
+                } else {
+                    self.metrics
+                        .track(
+                            "SetAVTransportURI",
+                            speaker.set_transport_uri(&uri, &metadata),
+                        )
+                        .await?;
+                    self.metrics.track("Play", speaker.play()).await?;
+                    self.notify(Notification::info(format!("Playing: {}", item.title)))
+                        .await;
+                }
+                Ok(true)
+            }
+
+            // Local files
+            Action::PlayFiles { paths, enqueue } => {
+                let files = file_server::audio_files(&paths)?;
+                anyhow::ensure!(!files.is_empty(), "No audio files found");
+                let coordinator = self
+                    .groups
+                    .get(self.selected_group)
+                    .context("No selected group")?
+                    .coordinator
+                    .clone();
+                let speaker = self.current_speaker().context("No selected group")?.clone();
+                if self.file_server.is_none() {
+                    let ip = speaker
+                        .device()
+                        .url()
+                        .host()
+                        .and_then(|host| host.parse().ok())
+                        .context("Failed to find the address of the speaker")?;
+                    self.file_server = Some(FileServer::start(ip).await?);
+                }
+                let server = self
+                    .file_server
+                    .as_ref()
+                    .expect("file server was just started");
+
+                let mut first_track = None;
+                let mut title = String::new();
+                for file in &files {
+                    let item = media::file_item(file, server.url(file)).await;
+                    let (uri, metadata) = (xml_escape(&item.uri), xml_escape(&item.metadata));
+                    let track = self
+                        .metrics
+                        .track(
+                            "AddURIToQueue",
+                            add_uri_to_queue(&speaker, &uri, &metadata, false),
+                        )
+                        .await?;
+                    first_track.get_or_insert(track);
+                    title = item.title;
+                }
+                let what = if files.len() == 1 {
+                    title
+                } else {
+                    format!("{} files", files.len())
+                };
+
+                if enqueue {
+                    self.notify(Notification::info(format!("Added to the queue: {what}")))
+                        .await;
+                } else {
+                    self.metrics
+                        .track(
+                            "SetAVTransportURI",
+                            speaker
+                                .set_transport_uri(&format!("x-rincon-queue:{coordinator}#0"), ""),
+                        )
+                        .await?;
+                    if let Some(track) = first_track {
+                        self.metrics
+                            .track("Seek", speaker.seek_track(track))
+                            .await?;
+                    }
+                    self.metrics.track("Play", speaker.play()).await?;
+                    self.notify(Notification::info(format!("Playing: {what}")))
+                        .await;
+                }
+                Ok(true)
+            }
+
+            // Announcements
+            Action::Announce { url, volume } => {
+                let item = media::url_item(&url).await?;
+                anyhow::ensure!(!item.is_stream, "Streams can't be used as announcements");
+                let group = self
+                    .groups
+                    .get(self.selected_group)
+                    .context("No selected group")?;
+                let coordinator = group.coordinator.clone();
+                // The snapshot would only record the other announcement
+                self.announcements.retain(|_, task| !task.is_finished());
+                anyhow::ensure!(
+                    !self.announcements.contains_key(&coordinator),
+                    "Another announcement is playing on {}",
+                    group.name()
+                );
+                let snapshot = self.snapshot().await?;
+                if let Err(err) = self.start_announcement(&item, volume).await {
+                    // Resume what was playing even if the announcement failed
+                    if let Err(err) = self.restore(&snapshot).await {
+                        warn!(%err, "Failed to resume playback");
+                    }
+                    return Err(err);
+                }
+                let speaker = self.current_speaker().context("No selected group")?;
+                let task = tokio::spawn(finish_announcement(
+                    self.metrics.clone(),
+                    self.speakers_by_uuid.clone(),
+                    speaker.clone(),
+                    snapshot,
+                    item.title,
+                    self.update_tx.clone(),
+                ));
+                self.announcements.insert(coordinator, task);
+                Ok(true)
+            }
+
+            Action::TransferPlayback {
+                to,
+                stop_source: true,
+            } => {
+                let name = self.move_playback(to).await?;
+                self.notify(Notification::info(format!("Moved playback to {name}")))
+                    .await;
+                Ok(true)
+            }
+            Action::TransferPlayback {
+                to,
+                stop_source: false,
+            } => {
+                self.copy_playback(to).await?;
+                Ok(true)
+            }
+
+            // Scenes
+            Action::ApplyScene(name) => {
+                let scene = self
+                    .scenes
+                    .get(&name)
+                    .with_context(|| format!("No scene named '{name}'"))?
+                    .clone();
+                self.apply_scene(&scene).await?;
+                self.notify(Notification::info(format!("Applied scene: {name}")))
+                    .await;
+                Ok(true)
+            }
+            Action::SaveScene(name) => {
+                anyhow::ensure!(
+                    !self.scenes.contains_key(&name),
+                    "There is already a scene named '{name}'"
+                );
+                let path = self
+                    .config_path
+                    .clone()
+                    .context("Couldn't find where the configuration file goes")?;
+                let scene = self.capture_scene().await?;
+                scene::save(&path, &name, &scene)?;
+                self.scenes.insert(name.clone(), scene);
+                self.notify(Notification::info(format!(
+                    "Saved scene {name} to {}",
+                    path.display()
+                )))
+                .await;
+                Ok(true)
+            }
+
+            // Inputs
+            Action::PlayInput(index) => {
+                let input = self.inputs.get(index).context("No such input")?;
+                let speaker = self.current_speaker().context("No selected group")?;
+                self.metrics
+                    .track(
+                        "SetAVTransportURI",
+                        speaker.set_transport_uri(&input.uri, ""),
+                    )
+                    .await?;
+                self.metrics.track("Play", speaker.play()).await?;
+                self.notify(Notification::info(format!("Playing: {}", input.name)))
+                    .await;
+                Ok(true)
+            }
+
+            // Alarms
+            Action::NavigateAlarms(offset) => {
+                let last = self.alarms.len().saturating_sub(1);
+                self.selected_alarm = self.selected_alarm.saturating_add_signed(offset).min(last);
+                Ok(false)
+            }
+            Action::SelectAlarm(index) => {
+                if index < self.alarms.len() {
+                    self.selected_alarm = index;
+                }
+                Ok(false)
+            }
+            Action::ToggleAlarm(index) => {
+                let mut alarm = self.alarms.get(index).context("No such alarm")?.clone();
+                alarm.enabled = !alarm.enabled;
+                let speaker = self.current_speaker().context("No selected group")?;
+                self.metrics
+                    .track("UpdateAlarm", alarms::save_alarm(speaker, &alarm))
+                    .await?;
+                let (hours, minutes) = alarm.time();
+                let state = if alarm.enabled { "enabled" } else { "disabled" };
+                self.notify(Notification::info(format!(
+                    "Alarm at {hours:02}:{minutes:02} {state}"
+                )))
+                .await;
+                self.refresh_alarms().await?;
+                Ok(false)
+            }
+            Action::SaveAlarm(mut alarm) => {
+                alarm.volume = alarm.volume.min(self.max_volume_of(&alarm.room_uuid));
+                let speaker = self.current_speaker().context("No selected group")?;
+                let name = if alarm.id.is_some() {
+                    "UpdateAlarm"
+                } else {
+                    "CreateAlarm"
+                };
+                self.metrics
+                    .track(name, alarms::save_alarm(speaker, &alarm))
+                    .await?;
+                let (hours, minutes) = alarm.time();
+                self.notify(Notification::info(format!(
+                    "Alarm at {hours:02}:{minutes:02} saved"
+                )))
+                .await;
+                self.refresh_alarms().await?;
+                // Follow the alarm to wherever it ended up in the list
+                if let Some(index) = self.alarms.iter().position(|a| {
+                    a.start_time == alarm.start_time && a.room_uuid == alarm.room_uuid
+                }) {
+                    self.selected_alarm = index;
+                }
+                Ok(false)
+            }
+            Action::DeleteAlarm(id) => {
+                // The list may have been refreshed while the deletion was being confirmed
+                let alarm = self
+                    .alarms
+                    .iter()
+                    .find(|a| a.id.as_ref() == Some(&id))
+                    .context("No such alarm")?;
+                let (hours, minutes) = alarm.time();
+                let speaker = self.current_speaker().context("No selected group")?;
+                self.metrics
+                    .track("DestroyAlarm", alarms::delete_alarm(speaker, &id))
+                    .await?;
+                self.notify(Notification::info(format!(
+                    "Alarm at {hours:02}:{minutes:02} deleted"
+                )))
+                .await;
+                self.refresh_alarms().await?;
+                Ok(false)
+            }
+
+            // Run a command against a group other than the selected one
+            Action::ForGroup(coordinator, action) => {
+                let index = self
+                    .groups
+                    .iter()
+                    .position(|g| g.coordinator == coordinator)
+                    .context("Unknown group")?;
+                let selected = self
+                    .groups
+                    .get(std::mem::replace(&mut self.selected_group, index))
+                    .map(|g| g.coordinator.clone());
+                let result = Box::pin(self.handle_command(*action)).await;
+                // Unless the action selected another group itself, e.g. by moving the playback,
+                // go back to the one that was selected, wherever it is now
+                let unchanged = self
+                    .groups
+                    .get(self.selected_group)
+                    .is_some_and(|g| g.coordinator == coordinator);
+                if unchanged
+                    && let Some(index) = self
+                        .groups
+                        .iter()
+                        .position(|g| Some(&g.coordinator) == selected.as_ref())
+                {
+                    self.selected_group = index;
+                }
+                result
+            }
+
+            // Only meaningful while connecting
+            Action::Retry | Action::Nop => Ok(false),
         }
         .context("Error while handling command")
     }
 
+    /// Move what the selected group is playing to the group at index `to`, and select it. The
+    /// speakers of that group join the selected one and take over as its coordinator, so the
+    /// queue and the position come along, then the speakers of the selected group leave.
+    async fn move_playback(&mut self, to: usize) -> Result<String> {
+        let target_group = self.groups.get(to).context("No such group")?;
+        anyhow::ensure!(
+            to != self.selected_group,
+            "This group is already playing it"
+        );
+        anyhow::ensure!(
+            self.cached_transport_uri.is_some(),
+            "This group isn't playing anything"
+        );
+        let source_group = self
+            .groups
+            .get(self.selected_group)
+            .context("No selected group")?;
+        let source = self.current_speaker().context("No selected group")?;
+        let speaker = |uuid: &str| {
+            self.speakers_by_uuid
+                .get(uuid)
+                .with_context(|| format!("Speaker {uuid} was not found"))
+        };
+
+        for info in &target_group.speakers {
+            self.metrics
+                .track(
+                    "SetAVTransportURI",
+                    speaker(info.uuid())?
+                        .set_transport_uri(&format!("x-rincon:{}", source_group.coordinator), ""),
+                )
+                .await?;
+        }
+        for info in source_group.speakers.iter().skip(1) {
+            self.metrics
+                .track(
+                    "BecomeCoordinatorOfStandaloneGroup",
+                    speaker(info.uuid())?.leave(),
+                )
+                .await?;
+        }
+        let payload = format!(
+            "{INSTANCE_ID}<NewCoordinator>{}</NewCoordinator><RejoinGroup>0</RejoinGroup>",
+            target_group.coordinator
+        );
+        self.metrics
+            .track(
+                "DelegateGroupCoordinationTo",
+                source.action(AV_TRANSPORT, "DelegateGroupCoordinationTo", &payload),
+            )
+            .await?;
+
+        let name = target_group.name();
+        let coordinator = target_group.coordinator.clone();
+        self.refresh_groups().await?;
+        if let Some(index) = self
+            .groups
+            .iter()
+            .position(|g| g.coordinator == coordinator)
+        {
+            self.selected_group = index;
+            self.selected_queue_item = None;
+        }
+        Ok(name)
+    }
+
+    /// Play what the selected group is playing on the group at index `to` too, from the same
+    /// point, and select it. Queues are copied over track by track in the background, which
+    /// reports how it went.
+    async fn copy_playback(&mut self, to: usize) -> Result<()> {
+        let target_group = self.groups.get(to).context("No such group")?;
+        anyhow::ensure!(
+            to != self.selected_group,
+            "This group is already playing it"
+        );
+        anyhow::ensure!(
+            self.cached_transport_uri.is_some(),
+            "This group isn't playing anything"
+        );
+        let source = self.current_speaker().context("No selected group")?;
+        let target = self
+            .speakers_by_uuid
+            .get(&target_group.coordinator)
+            .context("The coordinator of the group was not found")?;
+        let playback = Playback::take(&self.metrics, source).await?;
+
+        tokio::spawn(copy_queue_and_resume(
+            self.metrics.clone(),
+            source.clone(),
+            target.clone(),
+            target_group.coordinator.clone(),
+            playback,
+            target_group.name(),
+            self.update_tx.clone(),
+        ));
+        self.selected_group = to;
+        self.selected_queue_item = None;
+        Ok(())
+    }
+
+    /// Form the groups of a scene, set its volumes and start playing its source on its first
+    /// group, which becomes the selected one.
+    async fn apply_scene(&mut self, scene: &Scene) -> Result<()> {
+        let rooms: Vec<_> = self
+            .groups
+            .iter()
+            .flat_map(|g| g.speakers.iter().map(move |s| (s, &g.coordinator)))
+            .collect();
+        let room = |name: &str| {
+            rooms
+                .iter()
+                .find(|(s, _)| s.name().eq_ignore_ascii_case(name))
+                .map(|(s, coordinator)| (s.uuid().to_owned(), (*coordinator).clone()))
+                .with_context(|| format!("No room named '{name}'"))
+        };
+        let speaker = |uuid: &str| {
+            self.speakers_by_uuid
+                .get(uuid)
+                .with_context(|| format!("Speaker {uuid} was not found"))
+        };
+
+        for group in &scene.groups {
+            let (coordinator, current) = room(&group[0])?;
+            let members = group[1..]
+                .iter()
+                .map(|name| room(name))
+                .collect::<Result<Vec<_>>>()?;
+            if current != coordinator {
+                self.metrics
+                    .track(
+                        "BecomeCoordinatorOfStandaloneGroup",
+                        speaker(&coordinator)?.leave(),
+                    )
+                    .await?;
+            } else if let Some(current_group) =
+                self.groups.iter().find(|g| g.coordinator == coordinator)
+            {
+                // Drop the speakers that aren't part of the scene
+                for member in current_group.speakers.iter().skip(1) {
+                    if !members.iter().any(|(uuid, _)| uuid == member.uuid()) {
+                        self.metrics
+                            .track(
+                                "BecomeCoordinatorOfStandaloneGroup",
+                                speaker(member.uuid())?.leave(),
+                            )
+                            .await?;
+                    }
+                }
+            }
+            for (uuid, current) in &members {
+                if *current != coordinator {
+                    self.metrics
+                        .track(
+                            "SetAVTransportURI",
+                            speaker(uuid)?
+                                .set_transport_uri(&format!("x-rincon:{coordinator}"), ""),
+                        )
+                        .await?;
+                }
+            }
+        }
+
+        for (name, volume) in &scene.volumes {
+            let (uuid, _) = room(name)?;
+            self.metrics
+                .track(
+                    "SetVolume",
+                    speaker(&uuid)?.set_volume((*volume).min(self.max_volume_of(&uuid))),
+                )
+                .await?;
+        }
+        let first_coordinator = match scene.groups.first() {
+            Some(group) => Some(room(&group[0])?.0),
+            None => None,
+        };
+
+        self.refresh_groups().await?;
+        if let Some(coordinator) = first_coordinator
+            && let Some(index) = self
+                .groups
+                .iter()
+                .position(|g| g.coordinator == coordinator)
+        {
+            self.selected_group = index;
+        }
+
+        if let Some(favorite) = &scene.favorite {
+            let index = self
+                .favorites
+                .iter()
+                .position(|f| f.title.eq_ignore_ascii_case(favorite))
+                .with_context(|| format!("No favorite named '{favorite}'"))?;
+            Box::pin(self.handle_command(Action::PlayFavorite(index))).await?;
+        } else if let Some(uri) = &scene.uri {
+            if uri.starts_with("http://") || uri.starts_with("https://") {
+                let url = uri.clone();
+                Box::pin(self.handle_command(Action::PlayUrl {
+                    url,
+                    enqueue: false,
+                }))
+                .await?;
+            } else {
+                let speaker = self.current_speaker().context("No selected group")?;
+                self.metrics
+                    .track(
+                        "SetAVTransportURI",
+                        speaker.set_transport_uri(&xml_escape(uri), ""),
+                    )
+                    .await?;
+                self.metrics.track("Play", speaker.play()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Record the current layout as a scene: the groups (the selected one first, so that it's the
+    /// one that plays), the volume of every room, and what the selected group is playing.
+    async fn capture_scene(&mut self) -> Result<Scene> {
+        // The speakers may have been regrouped from elsewhere since the last refresh
+        self.refresh_groups().await?;
+        self.refresh_state().await?;
+
+        let order = std::iter::once(self.selected_group)
+            .chain((0..self.groups.len()).filter(|i| *i != self.selected_group));
+        let mut scene = Scene::default();
+        for index in order {
+            let group = self.groups.get(index).context("No selected group")?;
+            // The coordinator always comes first
+            scene
+                .groups
+                .push(group.speakers.iter().map(|s| s.name().to_owned()).collect());
+            for info in &group.speakers {
+                let speaker = self
+                    .speakers_by_uuid
+                    .get(info.uuid())
+                    .with_context(|| format!("Speaker {} was not found", info.uuid()))?;
+                let volume = self.metrics.track("GetVolume", speaker.volume()).await?;
+                scene.volumes.insert(info.name().to_owned(), volume);
+            }
+        }
+
+        if let Some(uri) = &self.cached_transport_uri {
+            match self
+                .favorites
+                .iter()
+                .find(|f| html_unescape(&f.uri) == *uri)
+            {
+                Some(favorite) => scene.favorite = Some(favorite.title.clone()),
+                None => scene.uri = Some(uri.clone()),
+            }
+        }
+        Ok(scene)
+    }
+
+    /// Fetch the groups again after they changed, keeping the same group selected if it's still
+    /// there.
+    async fn refresh_groups(&mut self) -> Result<()> {
+        let speaker = self
+            .speakers_by_uuid
+            .values()
+            .next()
+            .context("No speakers")?;
+        let topology = self
+            .metrics
+            .track("GetZoneGroupState", speaker.zone_group_state())
+            .await?;
+        let selected = self
+            .groups
+            .get(self.selected_group)
+            .map(|g| g.coordinator.clone());
+        let mut groups: Vec<_> = topology
+            .into_iter()
+            .map(|(uuid, speakers)| SpeakerGroup::new(uuid, speakers))
+            .collect();
+        // Keep the groups that are still there where they were, so the tabs don't move around
+        groups.sort_by_key(|g| {
+            self.groups
+                .iter()
+                .position(|old| old.coordinator == g.coordinator)
+                .unwrap_or(usize::MAX)
+        });
+        self.groups = groups;
+        self.selected_group = selected
+            .and_then(|c| self.groups.iter().position(|g| g.coordinator == c))
+            .unwrap_or(0);
+        self.selected_queue_item = None;
+        Ok(())
+    }
+
+    /// Record the state of the selected group, to put it back with [`Self::restore`] after
+    /// playing something else on it.
+    async fn snapshot(&self) -> Result<Snapshot> {
+        let group = self
+            .groups
+            .get(self.selected_group)
+            .context("No selected group")?;
+        // The coordinator always comes first
+        let members: Vec<_> = group.speakers.iter().map(|s| s.uuid().to_owned()).collect();
+        Snapshot::take(&self.metrics, &self.speakers_by_uuid, &members, |uuid| {
+            self.max_volume_of(uuid)
+        })
+        .await
+        .context("Failed to take a snapshot of the group")
+    }
+
+    async fn restore(&self, snapshot: &Snapshot) -> Result<()> {
+        snapshot
+            .restore(&self.metrics, &self.speakers_by_uuid)
+            .await
+    }
+
+    /// Play a clip on the selected group. Waiting for it to be over is left to
+    /// [`finish_announcement`], so that the other commands aren't held up in the meantime.
+    async fn start_announcement(&self, item: &media::MediaItem, volume: Option<u16>) -> Result<()> {
+        let group = self
+            .groups
+            .get(self.selected_group)
+            .context("No selected group")?;
+        let speaker = self.current_speaker().context("No selected group")?;
+        for info in &group.speakers {
+            let Some(member) = self.speakers_by_uuid.get(info.uuid()) else {
+                continue;
+            };
+            let max = self.max_volume_of(info.uuid());
+            self.metrics
+                .track("SetMute", member.set_mute(false))
+                .await?;
+            if let Some(volume) = volume {
+                self.metrics
+                    .track("SetVolume", member.set_volume(volume.min(max)))
+                    .await?;
+            }
+        }
+        self.metrics
+            .track(
+                "SetAVTransportURI",
+                speaker.set_transport_uri(&xml_escape(&item.uri), &xml_escape(&item.metadata)),
+            )
+            .await?;
+        self.metrics.track("Play", speaker.play()).await?;
+        Ok(())
+    }
+
     async fn refresh_state(&mut self) -> Result<()> {
         let uuid = self
             .groups
@@ -313,13 +1462,159 @@ impl SonosService {
             .context("Speaker not found")?
             .clone();
 
-        self.cached_is_playing = speaker.is_playing().await?;
-        self.cached_volume = speaker.volume().await?;
-        self.cached_now_playing = speaker.track().await?.map(Arc::new);
-        self.cached_queue = Arc::new(speaker.queue().await?);
+        let metrics = &self.metrics;
+        self.cached_is_playing = metrics
+            .track("GetTransportInfo", speaker.is_playing())
+            .await?;
+        self.cached_volume = metrics.track("GetVolume", speaker.volume()).await?;
+        self.cached_now_playing = metrics
+            .track("GetPositionInfo", speaker.track())
+            .await?
+            .map(Arc::new);
+        self.cached_album_art = self
+            .cached_now_playing
+            .as_ref()
+            .and_then(|track| album_art_url(&speaker, track));
+        let mut media_info = metrics
+            .track(
+                "GetMediaInfo",
+                speaker.action(AV_TRANSPORT, "GetMediaInfo", INSTANCE_ID),
+            )
+            .await?;
+        self.cached_transport_uri = media_info.remove("CurrentURI").filter(|u| !u.is_empty());
+        self.cached_media_metadata = media_info
+            .remove("CurrentURIMetaData")
+            .filter(|m| !m.is_empty());
+        self.cached_queue = Arc::new(metrics.track("Browse", speaker.queue()).await?);
+        // Keep the indicator of the selected group in step between two refreshes of all of them
+        if let Some(indicator) = self.cached_indicators.get_mut(&uuid) {
+            if self.cached_is_playing {
+                indicator.state = PlaybackState::Playing;
+            } else if indicator.state == PlaybackState::Playing {
+                indicator.state = PlaybackState::Paused;
+            }
+        }
+        Ok(())
+    }
+
+    /// The line-in of the speakers that have one, and the TV input of the home theater ones.
+    fn find_inputs(&self) -> Vec<AudioInput> {
+        let mut inputs = vec![];
+        for (uuid, speaker) in &self.speakers_by_uuid {
+            let name = self
+                .groups
+                .iter()
+                .flat_map(|g| &g.speakers)
+                .find(|s| s.uuid() == uuid)
+                .map_or_else(
+                    || speaker.device().friendly_name().to_owned(),
+                    |s| s.name().to_owned(),
+                );
+            let device = speaker.device();
+            if device.find_service(AUDIO_IN).is_some() {
+                inputs.push(AudioInput {
+                    name: format!("{name} (Line-in)"),
+                    uri: format!("x-rincon-stream:{uuid}"),
+                });
+            }
+            if device.find_service(HT_CONTROL).is_some() {
+                inputs.push(AudioInput {
+                    name: format!("{name} (TV)"),
+                    uri: format!("x-sonos-htastream:{uuid}:spdif"),
+                });
+            }
+        }
+        inputs
+    }
+
+    /// The status of all the groups is needed by the overview, and by the MQTT bridge and
+    /// metrics endpoint when they're enabled.
+    fn wants_group_statuses(&self) -> bool {
+        !self.status_tx.is_closed() || self.current_view == ViewMode::Overview
+    }
+
+    async fn refresh_group_statuses(&mut self) {
+        // Query all the coordinators at the same time
+        let metrics = &self.metrics;
+        let fetches = self.groups.iter().filter_map(|group| {
+            let speaker = self.speakers_by_uuid.get(&group.coordinator)?;
+            Some(async move {
+                let status = fetch_group_status(metrics, group, speaker).await;
+                (group, status)
+            })
+        });
+        let mut statuses = Vec::with_capacity(self.groups.len());
+        for (group, status) in join_all(fetches).await {
+            match status {
+                Ok(status) => statuses.push(status),
+                Err(e) => warn!("Failed to fetch status of group {}: {}", group.name(), e),
+            }
+        }
+        self.status_tx.send_replace(statuses);
+    }
+
+    async fn refresh_indicators(&mut self) {
+        let metrics = &self.metrics;
+        let fetches = self.groups.iter().filter_map(|group| {
+            let speaker = self.speakers_by_uuid.get(&group.coordinator)?;
+            Some(async move {
+                let indicator = fetch_group_indicator(metrics, speaker).await;
+                (group, indicator)
+            })
+        });
+        let mut indicators = BTreeMap::new();
+        for (group, indicator) in join_all(fetches).await {
+            match indicator {
+                Ok(indicator) => {
+                    indicators.insert(group.coordinator.clone(), indicator);
+                }
+                Err(e) => debug!("Failed to fetch indicator of group {}: {}", group.name(), e),
+            }
+        }
+        self.cached_indicators = indicators;
+    }
+
+    /// Alarms are shared by all the speakers, so any of them can list them.
+    async fn refresh_alarms(&mut self) -> Result<()> {
+        let speaker = self.current_speaker().context("No selected group")?;
+        self.alarms = self
+            .metrics
+            .track("ListAlarms", alarms::list_alarms(speaker))
+            .await?;
+        self.selected_alarm = self.selected_alarm.min(self.alarms.len().saturating_sub(1));
         Ok(())
     }
 
+    /// Check in the background which speakers still answer, so that the unreachable ones don't
+    /// hold up the commands.
+    fn check_reachability(&self) {
+        tokio::spawn(check_reachability(
+            self.metrics.clone(),
+            self.speakers_by_uuid.clone(),
+        ));
+    }
+
+    async fn notify(&self, notification: Notification) {
+        if let Err(err) = self
+            .update_tx
+            .send(Update::Notification(notification))
+            .await
+        {
+            warn!(%err, "Updates channel was closed: exiting");
+        }
+    }
+
+    async fn notify_error(&self, err: &anyhow::Error) {
+        // Skip the generic "Error while handling command" context
+        let message = err
+            .chain()
+            .skip(1)
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(": ");
+        self.notify(Notification::error(message)).await;
+    }
+
     async fn send_update(&self) {
         match self.build_state() {
             Ok(speaker_state) => {
@@ -335,19 +1630,62 @@ impl SonosService {
         }
     }
 
-    fn select_prev_group(&mut self) {
-        if self.selected_group == 0 {
-            self.selected_group = self.groups.len();
-        } else {
-            self.selected_group -= 1;
-        }
+    /// Position of the track currently playing in the queue
+    fn playing_queue_item(&self) -> Option<usize> {
+        let playing = self.cached_now_playing.as_ref()?;
+        self.cached_queue
+            .iter()
+            .position(|t| t.uri() == playing.track().uri())
+    }
+
+    /// UUID and speaker of the coordinator of the selected group.
+    fn current_coordinator(&self) -> Result<(&str, &Speaker)> {
+        let group = self
+            .groups
+            .get(self.selected_group)
+            .context("No selected group")?;
+        let speaker = self
+            .speakers_by_uuid
+            .get(&group.coordinator)
+            .context("No selected group")?;
+        Ok((&group.coordinator, speaker))
     }
 
-    fn select_next_group(&mut self) {
-        self.selected_group += 1;
-        if self.selected_group >= self.groups.len() {
-            self.selected_group = 0;
+    /// Highest volume a speaker can be set to.
+    fn max_volume_of(&self, uuid: &str) -> u16 {
+        let name = self
+            .groups
+            .iter()
+            .flat_map(|g| &g.speakers)
+            .find(|s| s.uuid() == uuid)
+            .map(|s| s.name());
+        name.and_then(|name| {
+            self.max_volume
+                .iter()
+                .find(|(room, _)| room.eq_ignore_ascii_case(name))
+        })
+        .map_or(100, |(_, max)| (*max).min(100))
+    }
+
+    /// The speakers of the selected group with their current volume, within their limits, to fade
+    /// them.
+    async fn group_levels(&self) -> Result<Vec<Level>> {
+        let group = self
+            .groups
+            .get(self.selected_group)
+            .context("No selected group")?;
+        let mut levels = vec![];
+        for info in &group.speakers {
+            let Some(speaker) = self.speakers_by_uuid.get(info.uuid()) else {
+                continue;
+            };
+            let volume = self.metrics.track("GetVolume", speaker.volume()).await?;
+            levels.push(Level {
+                speaker: speaker.clone(),
+                volume: volume.min(self.max_volume_of(info.uuid())),
+            });
         }
+        Ok(levels)
     }
 
     fn current_speaker(&self) -> Option<&Speaker> {
@@ -362,50 +1700,78 @@ impl SonosService {
         for group in &self.groups {
             names.push(group.name());
         }
+        let group_statuses = {
+            let statuses = self.status_tx.borrow();
+            self.groups
+                .iter()
+                .map(|group| {
+                    statuses
+                        .iter()
+                        .find(|s| s.coordinator == group.coordinator)
+                        .cloned()
+                })
+                .collect()
+        };
 
         Ok(SpeakerState {
-            is_playing: self.cached_is_playing,
+            // Fading out means it's about to pause
+            is_playing: self.cached_is_playing
+                && !self
+                    .groups
+                    .get(self.selected_group)
+                    .and_then(|group| self.fades.get(&group.coordinator))
+                    .is_some_and(Fade::is_pausing),
             current_volume: self.cached_volume,
             group_names: names,
+            group_statuses,
+            group_indicators: self
+                .groups
+                .iter()
+                .map(|group| self.cached_indicators.get(&group.coordinator).copied())
+                .collect(),
             selected_group: self.selected_group,
             now_playing: self.cached_now_playing.clone(),
+            album_art: self.cached_album_art.clone(),
             queue: self.cached_queue.clone(),
+            selected_queue_item: self
+                .selected_queue_item
+                .filter(|i| *i < self.cached_queue.len()),
             current_view: self.current_view,
             favorites: self.favorites.clone(),
             selected_favorite: self.selected_favorite,
+            rooms: self
+                .groups
+                .iter()
+                .enumerate()
+                .flat_map(|(group, g)| {
+                    g.speakers.iter().map(move |s| Room {
+                        uuid: s.uuid().to_owned(),
+                        name: s.name().to_owned(),
+                        group,
+                    })
+                })
+                .collect(),
+            alarms: self.alarms.clone(),
+            selected_alarm: self.selected_alarm,
+            inputs: self.inputs.clone(),
+            playing_input: self.cached_transport_uri.as_ref().and_then(|uri| {
+                self.inputs
+                    .iter()
+                    .find(|input| input.uri == *uri)
+                    .map(|input| input.name.clone())
+            }),
+            radio: radio_info(
+                self.cached_transport_uri.as_deref(),
+                self.cached_media_metadata.as_deref(),
+                self.cached_now_playing.as_deref(),
+            ),
+            scenes: self.scenes.keys().cloned().collect(),
+            handled: self.handled,
+            pending: PendingChanges::default(),
         })
     }
 }
 
-async fn get_speakers(provided_devices: (Vec<Ipv4Addr>, Vec<String>)) -> Result<Vec<Speaker>> {
-    let mut speakers: Vec<Speaker> = vec![];
-    debug!("Connecting to provided speakers...");
-    for e in &provided_devices.0 {
-        if let Some(spk) = sonor::Speaker::from_ip(*e).await.unwrap_or(None) {
-            speakers.push(spk);
-        } else {
-            debug!("Not connecting to {e} due to errors");
-        }
-    }
-    for e in &provided_devices.1 {
-        if let Some(device) = sonor::find(e, Duration::from_secs(2)).await? {
-            speakers.push(device);
-        } else {
-            debug!("Not connecting to {e} due to errors");
-        }
-    }
-    if provided_devices.0.is_empty() && provided_devices.1.is_empty() {
-        debug!("Discovering speakers...");
-        let mut devices = sonor::discover(Duration::from_secs(2)).await?;
-        while let Some(device) = devices.try_next().await? {
-            speakers.push(device);
-        }
-    }
-
-    info!("Found {} speakers", speakers.len());
-    Ok(speakers)
-}
-
 struct SpeakerGroup {
     coordinator: String,
     speakers: Vec<SpeakerInfo>,
@@ -435,6 +1801,309 @@ impl SpeakerGroup {
         let names: Vec<_> = self.speakers.iter().map(SpeakerInfo::name).collect();
         names.join(" + ")
     }
+
+    /// Whether this group is called `name`, or contains a speaker called `name`.
+    fn matches(&self, name: &str) -> bool {
+        self.name().eq_ignore_ascii_case(name)
+            || self
+                .speakers
+                .iter()
+                .any(|s| s.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Wait for the announcement playing on `coordinator` to be over, then put the group back the way
+/// it was.
+async fn finish_announcement(
+    metrics: Arc<Metrics>,
+    speakers: BTreeMap<String, Speaker>,
+    coordinator: Speaker,
+    snapshot: Snapshot,
+    title: String,
+    update_tx: Sender<Update>,
+) {
+    // Resume what was playing even if the announcement can't be followed
+    if let Err(err) =
+        snapshot::wait_until_stopped(&metrics, &coordinator, ANNOUNCEMENT_TIMEOUT).await
+    {
+        warn!(%err, "Failed to wait for the announcement to finish");
+    }
+    let notification = match snapshot.restore(&metrics, &speakers).await {
+        Ok(()) => Notification::info(format!("Announced: {title}")),
+        Err(err) => Notification::error(format!("Failed to resume playback: {err:#}")),
+    };
+    if let Err(err) = update_tx.send(Update::Notification(notification)).await {
+        warn!(%err, "Updates channel was closed");
+    }
+}
+
+/// Ask all the speakers for their name at the same time, and record which ones answered.
+async fn check_reachability(metrics: Arc<Metrics>, speakers: BTreeMap<String, Speaker>) {
+    let checks = speakers.iter().map(|(uuid, speaker)| {
+        let metrics = &metrics;
+        async move {
+            match metrics.track("GetZoneAttributes", speaker.name()).await {
+                Ok(name) => metrics.set_reachable(uuid, Some(name), true),
+                Err(e) => {
+                    debug!("Speaker {} is unreachable: {}", uuid, e);
+                    metrics.set_reachable(uuid, None, false);
+                }
+            }
+        }
+    });
+    join_all(checks).await;
+}
+
+/// Copy the queue of `source` over to `target` if that's what it's playing, then play the same
+/// thing on `target` (whose UUID is `target_uuid`).
+async fn copy_queue_and_resume(
+    metrics: Arc<Metrics>,
+    source: Speaker,
+    target: Speaker,
+    target_uuid: String,
+    playback: Playback,
+    name: String,
+    update_tx: Sender<Update>,
+) {
+    let copied = async {
+        if playback.is_queue() {
+            let items = fetch_queue_items(&metrics, &source).await?;
+            metrics
+                .track("RemoveAllTracksFromQueue", target.clear_queue())
+                .await?;
+            for (uri, metadata) in &items {
+                // The URIs are still escaped, as found in the DIDL-Lite document
+                metrics
+                    .track(
+                        "AddURIToQueue",
+                        add_uri_to_queue(&target, uri, &xml_escape(metadata), false),
+                    )
+                    .await?;
+            }
+        }
+        playback.resume(&metrics, &target, &target_uuid).await
+    };
+    let notification = match copied.await {
+        Ok(()) => Notification::info(format!("Copied playback to {name}")),
+        Err(err) => Notification::error(format!("Failed to copy playback to {name}: {err:#}")),
+    };
+    if let Err(err) = update_tx.send(Update::Notification(notification)).await {
+        warn!(%err, "Updates channel was closed");
+    }
+}
+
+async fn fetch_group_status(
+    metrics: &Metrics,
+    group: &SpeakerGroup,
+    speaker: &Speaker,
+) -> Result<GroupStatus> {
+    let (playing, volume, track, queue_length) = tokio::join!(
+        metrics.track("GetTransportInfo", speaker.is_playing()),
+        metrics.track("GetVolume", speaker.volume()),
+        metrics.track("GetPositionInfo", speaker.track()),
+        metrics.track("Browse", fetch_queue_length(speaker)),
+    );
+    let (playing, volume, track, queue_length) = (playing?, volume?, track?, queue_length?);
+
+    Ok(GroupStatus {
+        name: group.name(),
+        coordinator: group.coordinator.clone(),
+        playing,
+        volume,
+        title: track.as_ref().map(|t| t.track().title().to_string()),
+        artist: track
+            .as_ref()
+            .and_then(|t| t.track().creator().map(str::to_string)),
+        album: track
+            .as_ref()
+            .and_then(|t| t.track().album().map(str::to_string)),
+        elapsed: track.as_ref().map_or(0, TrackInfo::elapsed),
+        duration: track.as_ref().map_or(0, TrackInfo::duration),
+        queue_length,
+    })
+}
+
+async fn fetch_group_indicator(metrics: &Metrics, speaker: &Speaker) -> Result<GroupIndicator> {
+    let (transport, mute) = tokio::join!(
+        metrics.track(
+            "GetTransportInfo",
+            speaker.action(AV_TRANSPORT, "GetTransportInfo", INSTANCE_ID)
+        ),
+        metrics.track(
+            "GetGroupMute",
+            speaker.action(GROUP_RENDERING_CONTROL, "GetGroupMute", INSTANCE_ID)
+        ),
+    );
+    let state = match transport?
+        .get("CurrentTransportState")
+        .context("No CurrentTransportState in response")?
+        .as_str()
+    {
+        "PLAYING" | "TRANSITIONING" => PlaybackState::Playing,
+        "PAUSED_PLAYBACK" => PlaybackState::Paused,
+        _ => PlaybackState::Stopped,
+    };
+    let muted = mute?.get("CurrentMute").is_some_and(|m| m == "1");
+    Ok(GroupIndicator { state, muted })
+}
+
+async fn fetch_queue_length(speaker: &Speaker) -> Result<usize> {
+    let service = URN::service("schemas-sonos-com", "Queue", 1);
+    let payload = r#"<QueueID>0</QueueID>
+<StartingIndex>0</StartingIndex>
+<RequestedCount>1</RequestedCount>"#;
+
+    let response = speaker.action(&service, "Browse", payload).await?;
+    let total = response
+        .get("TotalMatches")
+        .context("No TotalMatches in browse response")?;
+    total
+        .parse()
+        .context("Invalid TotalMatches in browse response")
+}
+
+/// Merge the commands that were queued up: only the last of consecutive absolute commands (setting
+/// the volume, seeking, selecting an item) is kept, and consecutive relative ones (volume changes,
+/// group switches, moves through a list) add up while they go the same way. They don't add up
+/// across directions, as the volume and the lists stop at their limits: going up then down from
+/// the top isn't the same as staying put.
+fn coalesce(cmds: Vec<Action>) -> Vec<Action> {
+    let count = cmds.iter().filter(|c| !matches!(c, Action::Nop)).count();
+    let mut merged: Vec<Action> = vec![];
+    for cmd in cmds {
+        match (merged.last_mut(), cmd) {
+            (_, Action::Nop) => {}
+            (Some(Action::VolAdjust(total)), Action::VolAdjust(v))
+                if total.signum() == v.signum() =>
+            {
+                *total = total.saturating_add(v);
+            }
+            (Some(Action::CycleGroup(total)), Action::CycleGroup(n))
+            | (Some(Action::NavigateQueue(total)), Action::NavigateQueue(n))
+            | (Some(Action::NavigateFavorites(total)), Action::NavigateFavorites(n))
+            | (Some(Action::NavigateAlarms(total)), Action::NavigateAlarms(n))
+                if total.signum() == n.signum() =>
+            {
+                *total = total.saturating_add(n);
+            }
+            (Some(last), cmd) if supersedes(&cmd, last) => *last = cmd,
+            (_, cmd) => merged.push(cmd),
+        }
+    }
+    merged.retain(|cmd| !matches!(cmd, Action::VolAdjust(0) | Action::CycleGroup(0)));
+    if merged.len() < count {
+        debug!(count, merged = merged.len(), "Merged queued commands");
+    }
+    merged
+}
+
+/// Whether running `cmd` right after `previous` makes `previous` pointless.
+fn supersedes(cmd: &Action, previous: &Action) -> bool {
+    matches!(
+        cmd,
+        Action::SetVolume(_)
+            | Action::Seek(_)
+            | Action::SelectGroup(_)
+            | Action::SelectQueueItem(_)
+            | Action::SelectFavorite(_)
+            | Action::SelectAlarm(_)
+            | Action::SwitchView(_)
+    ) && std::mem::discriminant(cmd) == std::mem::discriminant(previous)
+}
+
+/// Whether a command takes over from a fade in progress: anything that changes what's playing or
+/// how loud, but not e.g. moving around the lists.
+fn cancels_fade(cmd: &Action) -> bool {
+    matches!(
+        cmd,
+        Action::Play
+            | Action::Pause
+            | Action::Next
+            | Action::Prev
+            | Action::VolAdjust(_)
+            | Action::SetVolume(_)
+            | Action::Seek(_)
+            | Action::PlayQueueItem(_)
+            | Action::PlayFavorite(_)
+            | Action::PlayInput(_)
+            | Action::PlayUrl { .. }
+            | Action::PlayFiles { .. }
+            | Action::Announce { .. }
+            | Action::TransferPlayback { .. }
+            | Action::ApplyScene(_)
+    )
+}
+
+/// Add a URI to the queue, at the end or after the current track, and get the position of the
+/// first track added. Unlike [`Speaker::queue_end`], this works for containers like playlists.
+///
+/// The URI and metadata have to be XML escaped already.
+async fn add_uri_to_queue(
+    speaker: &Speaker,
+    uri: &str,
+    metadata: &str,
+    as_next: bool,
+) -> Result<u32> {
+    let payload = format!(
+        r#"<InstanceID>0</InstanceID>
+<EnqueuedURI>{uri}</EnqueuedURI>
+<EnqueuedURIMetaData>{metadata}</EnqueuedURIMetaData>
+<DesiredFirstTrackNumberEnqueued>0</DesiredFirstTrackNumberEnqueued>
+<EnqueueAsNext>{}</EnqueueAsNext>"#,
+        as_next as u8
+    );
+    let response = speaker
+        .action(AV_TRANSPORT, "AddURIToQueue", &payload)
+        .await?;
+    response
+        .get("FirstTrackNumberEnqueued")
+        .context("No FirstTrackNumberEnqueued in AddURIToQueue response")?
+        .parse()
+        .context("Invalid FirstTrackNumberEnqueued in AddURIToQueue response")
+}
+
+/// The tracks of the queue, with the DIDL-Lite metadata needed to add them to another queue
+/// (music services need it to play them).
+async fn fetch_queue_items(metrics: &Metrics, speaker: &Speaker) -> Result<Vec<(String, String)>> {
+    let service = URN::service("schemas-upnp-org", "ContentDirectory", 1);
+    let mut items = vec![];
+    // The speakers return at most 100 items at a time
+    loop {
+        let payload = format!(
+            r#"<ObjectID>Q:0</ObjectID>
+<BrowseFlag>BrowseDirectChildren</BrowseFlag>
+<Filter>*</Filter>
+<StartingIndex>{}</StartingIndex>
+<RequestedCount>100</RequestedCount>
+<SortCriteria></SortCriteria>"#,
+            items.len()
+        );
+        let response = metrics
+            .track("Browse", speaker.action(&service, "Browse", &payload))
+            .await
+            .context("Failed to browse the queue")?;
+        let total: usize = response
+            .get("TotalMatches")
+            .and_then(|t| t.parse().ok())
+            .context("Invalid TotalMatches in browse response")?;
+        let xml = response
+            .get("Result")
+            .context("No Result in browse response")?;
+
+        let count = items.len();
+        items.extend(xml.split("<item ").skip(1).filter_map(|item| {
+            let item = &item[..item.find("</item>")?];
+            let uri = extract_tag_content(item, "<res", "</res>")
+                .and_then(|res| res.find('>').map(|start| &res[start + 1..]))?;
+            Some((
+                uri.to_owned(),
+                media::didl_lite(&format!("<item {item}</item>")),
+            ))
+        }));
+        if items.len() >= total || items.len() == count {
+            return Ok(items);
+        }
+    }
 }
 
 async fn fetch_favorite_playlists(speaker: &Speaker) -> Result<Vec<FavoritePlaylist>> {
@@ -503,17 +2172,192 @@ fn parse_favorite_playlists(xml: &str) -> Vec<FavoritePlaylist> {
     playlists
 }
 
-fn extract_tag_content<'a>(text: &'a str, start_tag: &str, end_tag: &str) -> Option<&'a str> {
+/// Radio stations are played from a stream URI, and tell what's playing in the metadata of the
+/// track rather than in its title.
+fn radio_info(
+    uri: Option<&str>,
+    media_metadata: Option<&str>,
+    track: Option<&TrackInfo>,
+) -> Option<RadioInfo> {
+    const RADIO_SCHEMES: &[&str] = &[
+        "x-sonosapi-stream:",
+        "x-sonosapi-radio:",
+        "x-sonosapi-hls:",
+        "x-rincon-mp3radio:",
+        "hls-radio:",
+        "aac:",
+    ];
+    let track_metadata = track.map_or("", TrackInfo::metadata);
+    let tag = |xml: &str, name: &str| {
+        extract_tag_content(xml, &format!("<{name}>"), &format!("</{name}>"))
+            .map(|content| html_unescape(content.trim()))
+            .filter(|content| !content.is_empty())
+    };
+    let song = tag(track_metadata, "r:streamContent")
+        // e.g. ZPSTR_CONNECTING while the stream is starting
+        .filter(|song| !song.starts_with("ZPSTR_"));
+    let is_radio = uri.is_some_and(|uri| RADIO_SCHEMES.iter().any(|s| uri.starts_with(s)))
+        || track_metadata.contains("<r:streamContent>");
+    if !is_radio {
+        return None;
+    }
+
+    let station = media_metadata
+        .and_then(|metadata| tag(metadata, "dc:title"))
+        .or_else(|| track.map(|t| t.track().title().to_owned()))
+        .unwrap_or_else(|| "Radio".to_owned());
+    // The show comes with an ID, e.g. "Morning Show,p123456"
+    let show = tag(track_metadata, "r:radioShowMd").map(|show| match show.rsplit_once(',') {
+        Some((name, _)) => name.to_owned(),
+        None => show,
+    });
+    Some(RadioInfo {
+        station,
+        song,
+        show,
+    })
+}
+
+/// The album art of a track is usually served by the speaker itself, at a path relative to it.
+fn album_art_url(speaker: &Speaker, track: &TrackInfo) -> Option<String> {
+    let uri = extract_tag_content(
+        track.metadata(),
+        "<upnp:albumArtURI>",
+        "</upnp:albumArtURI>",
+    )?;
+    let uri = html_unescape(uri.trim());
+    if uri.is_empty() {
+        None
+    } else if uri.starts_with("http://") || uri.starts_with("https://") {
+        Some(uri)
+    } else {
+        let authority = speaker.device().url().authority()?;
+        Some(format!(
+            "http://{authority}/{}",
+            uri.trim_start_matches('/')
+        ))
+    }
+}
+
+pub fn extract_tag_content<'a>(text: &'a str, start_tag: &str, end_tag: &str) -> Option<&'a str> {
     let start = text.find(start_tag)?;
     let content_start = start + start_tag.len();
     let end = text[content_start..].find(end_tag)?;
     Some(&text[content_start..content_start + end])
 }
 
-fn html_unescape(s: &str) -> String {
+pub fn html_unescape(s: &str) -> String {
     s.replace("&amp;", "&")
         .replace("&lt;", "<")
         .replace("&gt;", ">")
         .replace("&quot;", "\"")
         .replace("&apos;", "'")
 }
+
+pub fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::coalesce;
+    use crate::{Action, ViewMode};
+
+    fn merged(cmds: Vec<Action>) -> String {
+        format!("{:?}", coalesce(cmds))
+    }
+
+    #[test]
+    fn coalesce_volume_changes() {
+        use Action::VolAdjust;
+        assert_eq!(
+            merged(vec![VolAdjust(2), VolAdjust(2), VolAdjust(3)]),
+            "[VolAdjust(7)]"
+        );
+        // The volume stops at its limits, so changes the other way don't cancel out
+        assert_eq!(
+            merged(vec![VolAdjust(2), VolAdjust(-2)]),
+            "[VolAdjust(2), VolAdjust(-2)]"
+        );
+        assert_eq!(
+            merged(vec![VolAdjust(2), VolAdjust(2), VolAdjust(-1)]),
+            "[VolAdjust(4), VolAdjust(-1)]"
+        );
+        assert_eq!(merged(vec![VolAdjust(0)]), "[]");
+        assert_eq!(
+            merged(vec![VolAdjust(i16::MAX), VolAdjust(1)]),
+            "[VolAdjust(32767)]"
+        );
+    }
+
+    #[test]
+    fn coalesce_only_consecutive() {
+        use Action::{Pause, Play, VolAdjust};
+        assert_eq!(
+            merged(vec![VolAdjust(2), Pause, VolAdjust(2)]),
+            "[VolAdjust(2), Pause, VolAdjust(2)]"
+        );
+        assert_eq!(merged(vec![Play, Pause, Play]), "[Play, Pause, Play]");
+        assert_eq!(merged(vec![Action::Next, Action::Next]), "[Next, Next]");
+        // Nothing to do isn't in the way
+        assert_eq!(
+            merged(vec![VolAdjust(1), Action::Nop, VolAdjust(1)]),
+            "[VolAdjust(2)]"
+        );
+    }
+
+    #[test]
+    fn coalesce_absolute_commands() {
+        use Action::{Seek, SelectFavorite, SetVolume};
+        assert_eq!(
+            merged(vec![SetVolume(10), SetVolume(20)]),
+            "[SetVolume(20)]"
+        );
+        assert_eq!(merged(vec![Seek(5), Seek(30), Seek(12)]), "[Seek(12)]");
+        assert_eq!(
+            merged(vec![SelectFavorite(1), SelectFavorite(4)]),
+            "[SelectFavorite(4)]"
+        );
+        assert_eq!(
+            merged(vec![SetVolume(10), Seek(5), SetVolume(20)]),
+            "[SetVolume(10), Seek(5), SetVolume(20)]"
+        );
+        assert_eq!(
+            merged(vec![
+                Action::SwitchView(ViewMode::Queue),
+                Action::SwitchView(ViewMode::Alarms)
+            ]),
+            "[SwitchView(Alarms)]"
+        );
+    }
+
+    #[test]
+    fn coalesce_navigation() {
+        use Action::{CycleGroup, NavigateAlarms, NavigateFavorites, NavigateQueue};
+        assert_eq!(
+            merged(vec![NavigateQueue(1), NavigateQueue(1), NavigateQueue(1)]),
+            "[NavigateQueue(3)]"
+        );
+        assert_eq!(
+            merged(vec![NavigateFavorites(-1), NavigateFavorites(-1)]),
+            "[NavigateFavorites(-2)]"
+        );
+        // The selection stops at the ends, so moves the other way don't cancel out
+        assert_eq!(
+            merged(vec![NavigateAlarms(-1), NavigateAlarms(1)]),
+            "[NavigateAlarms(-1), NavigateAlarms(1)]"
+        );
+        assert_eq!(
+            merged(vec![NavigateQueue(1), NavigateFavorites(1)]),
+            "[NavigateQueue(1), NavigateFavorites(1)]"
+        );
+        assert_eq!(
+            merged(vec![CycleGroup(1), CycleGroup(1), CycleGroup(-1)]),
+            "[CycleGroup(2), CycleGroup(-1)]"
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result, anyhow, bail};
+use ratatui::style::{Color, Modifier, Style};
+use serde_derive::Deserialize;
+
+/// Styles used to render the UI.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    /// "Sinuous x.y.z" in the title bar
+    pub title: Style,
+    /// Name of the selected group in the title bar
+    pub group: Style,
+    /// Selected group in the Groups tab bar
+    pub group_tab: Style,
+    /// Selected view in the view tab bar
+    pub view_tab: Style,
+    /// Currently playing track in the queue
+    pub queue_highlight: Style,
+    /// Selected track in the queue
+    pub queue_selection: Style,
+    /// Selected favorite
+    pub favorite_highlight: Style,
+    /// Progress bar of the current track
+    pub gauge: Style,
+    /// Characters matching the filter in the lists
+    pub filter_match: Style,
+    /// Key hints at the bottom of the lists
+    pub hint: Style,
+    /// Borders of the different blocks
+    pub border: Style,
+    /// Border of informational notifications
+    pub notification_info: Style,
+    /// Border of error notifications
+    pub notification_error: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            title: Style::default()
+                .fg(Color::Yellow)
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+            group: Style::default().fg(Color::Green),
+            group_tab: Style::default().fg(Color::Green),
+            view_tab: Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+            queue_highlight: Style::default().fg(Color::LightMagenta),
+            queue_selection: Style::default().bg(Color::DarkGray),
+            favorite_highlight: Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+            gauge: Style::default()
+                .fg(Color::LightGreen)
+                .bg(Color::Black)
+                .add_modifier(Modifier::ITALIC),
+            filter_match: Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+            hint: Style::default().fg(Color::DarkGray),
+            border: Style::default(),
+            notification_info: Style::default().fg(Color::Green),
+            notification_error: Style::default().fg(Color::Red),
+        }
+    }
+}
+
+impl Theme {
+    pub const BUILTIN: &[&str] = &[
+        "default",
+        "ocean",
+        "solarized",
+        "monochrome",
+        "high-contrast",
+    ];
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        let theme = match name {
+            "default" => Self::default(),
+            "ocean" => Self {
+                title: Style::default()
+                    .fg(Color::White)
+                    .bg(Color::Blue)
+                    .add_modifier(Modifier::BOLD),
+                group: Style::default().fg(Color::LightCyan),
+                group_tab: Style::default().fg(Color::LightCyan),
+                view_tab: Style::default()
+                    .fg(Color::LightBlue)
+                    .add_modifier(Modifier::BOLD),
+                queue_highlight: Style::default().fg(Color::LightCyan),
+                queue_selection: Style::default().bg(Color::Blue),
+                favorite_highlight: Style::default()
+                    .fg(Color::LightBlue)
+                    .add_modifier(Modifier::BOLD),
+                gauge: Style::default().fg(Color::Cyan).bg(Color::Black),
+                filter_match: Style::default()
+                    .fg(Color::LightYellow)
+                    .add_modifier(Modifier::BOLD),
+                hint: Style::default().fg(Color::Blue),
+                border: Style::default().fg(Color::Blue),
+                notification_info: Style::default().fg(Color::LightCyan),
+                notification_error: Style::default().fg(Color::LightRed),
+            },
+            "solarized" => Self {
+                title: Style::default()
+                    .fg(Color::Rgb(0xfd, 0xf6, 0xe3))
+                    .bg(Color::Rgb(0x26, 0x8b, 0xd2))
+                    .add_modifier(Modifier::BOLD),
+                group: Style::default().fg(Color::Rgb(0x85, 0x99, 0x00)),
+                group_tab: Style::default().fg(Color::Rgb(0x85, 0x99, 0x00)),
+                view_tab: Style::default()
+                    .fg(Color::Rgb(0x2a, 0xa1, 0x98))
+                    .add_modifier(Modifier::BOLD),
+                queue_highlight: Style::default().fg(Color::Rgb(0xd3, 0x36, 0x82)),
+                queue_selection: Style::default().bg(Color::Rgb(0x07, 0x36, 0x42)),
+                favorite_highlight: Style::default()
+                    .fg(Color::Rgb(0xb5, 0x89, 0x00))
+                    .add_modifier(Modifier::BOLD),
+                gauge: Style::default()
+                    .fg(Color::Rgb(0x85, 0x99, 0x00))
+                    .bg(Color::Rgb(0x07, 0x36, 0x42)),
+                filter_match: Style::default()
+                    .fg(Color::Rgb(0xcb, 0x4b, 0x16))
+                    .add_modifier(Modifier::BOLD),
+                hint: Style::default().fg(Color::Rgb(0x58, 0x6e, 0x75)),
+                border: Style::default().fg(Color::Rgb(0x58, 0x6e, 0x75)),
+                notification_info: Style::default().fg(Color::Rgb(0x85, 0x99, 0x00)),
+                notification_error: Style::default().fg(Color::Rgb(0xdc, 0x32, 0x2f)),
+            },
+            // Only uses text attributes, for terminals without colours or when NO_COLOR is set
+            "monochrome" => Self {
+                title: Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED),
+                group: Style::default().add_modifier(Modifier::BOLD),
+                group_tab: Style::default().add_modifier(Modifier::REVERSED),
+                view_tab: Style::default().add_modifier(Modifier::REVERSED),
+                queue_highlight: Style::default().add_modifier(Modifier::REVERSED),
+                queue_selection: Style::default().add_modifier(Modifier::UNDERLINED),
+                favorite_highlight: Style::default().add_modifier(Modifier::REVERSED),
+                gauge: Style::default(),
+                filter_match: Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                hint: Style::default().add_modifier(Modifier::DIM),
+                border: Style::default(),
+                notification_info: Style::default(),
+                notification_error: Style::default().add_modifier(Modifier::BOLD),
+            },
+            // Maximum contrast and no dim colours, for accessibility
+            "high-contrast" => Self {
+                title: Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::White)
+                    .add_modifier(Modifier::BOLD),
+                group: Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+                group_tab: Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+                view_tab: Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+                queue_highlight: Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+                queue_selection: Style::default()
+                    .fg(Color::White)
+                    .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                favorite_highlight: Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+                gauge: Style::default().fg(Color::Yellow).bg(Color::Black),
+                filter_match: Style::default()
+                    .fg(Color::LightYellow)
+                    .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                hint: Style::default().fg(Color::White),
+                border: Style::default().fg(Color::White),
+                notification_info: Style::default()
+                    .fg(Color::White)
+                    .add_modifier(Modifier::BOLD),
+                notification_error: Style::default()
+                    .fg(Color::LightRed)
+                    .add_modifier(Modifier::BOLD),
+            },
+            _ => return None,
+        };
+        Some(theme)
+    }
+
+    /// Build the theme from the `[theme]` section of the configuration file.
+    ///
+    /// When the `NO_COLOR` environment variable is set, the monochrome theme is used instead,
+    /// without any of the overrides.
+    pub fn from_config(config: &ThemeConfig) -> Result<Self> {
+        if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+            return Ok(Self::builtin("monochrome").expect("monochrome theme exists"));
+        }
+
+        let name = config.name.as_deref().unwrap_or("default");
+        let mut theme = Self::builtin(name).ok_or_else(|| {
+            anyhow!(
+                "unknown theme '{name}' (expected one of: {})",
+                Self::BUILTIN.join(", ")
+            )
+        })?;
+
+        for (element, style) in &config.styles {
+            let style = style
+                .to_style()
+                .with_context(|| format!("Invalid style for theme element '{element}'"))?;
+            let target = match element.as_str() {
+                "title" => &mut theme.title,
+                "group" => &mut theme.group,
+                "group_tab" => &mut theme.group_tab,
+                "view_tab" => &mut theme.view_tab,
+                "queue_highlight" => &mut theme.queue_highlight,
+                "queue_selection" => &mut theme.queue_selection,
+                "favorite_highlight" => &mut theme.favorite_highlight,
+                "gauge" => &mut theme.gauge,
+                "filter_match" => &mut theme.filter_match,
+                "hint" => &mut theme.hint,
+                "border" => &mut theme.border,
+                "notification_info" => &mut theme.notification_info,
+                "notification_error" => &mut theme.notification_error,
+                _ => bail!("unknown theme element '{element}'"),
+            };
+            *target = style;
+        }
+        Ok(theme)
+    }
+}
+
+/// The `[theme]` section of the configuration file: the name of a built-in theme, and styles
+/// overriding some of its elements.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ThemeConfig {
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub styles: BTreeMap<String, StyleConfig>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StyleConfig {
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    pub modifiers: Vec<String>,
+}
+
+impl StyleConfig {
+    fn to_style(&self) -> Result<Style> {
+        let mut style = Style::default();
+        if let Some(fg) = &self.fg {
+            style = style.fg(fg.parse().map_err(|_| anyhow!("invalid colour '{fg}'"))?);
+        }
+        if let Some(bg) = &self.bg {
+            style = style.bg(bg.parse().map_err(|_| anyhow!("invalid colour '{bg}'"))?);
+        }
+        for modifier in &self.modifiers {
+            style = style.add_modifier(match modifier.to_ascii_lowercase().as_str() {
+                "bold" => Modifier::BOLD,
+                "dim" => Modifier::DIM,
+                "italic" => Modifier::ITALIC,
+                "underlined" => Modifier::UNDERLINED,
+                "reversed" => Modifier::REVERSED,
+                "crossed_out" => Modifier::CROSSED_OUT,
+                _ => bail!("unknown modifier '{modifier}'"),
+            });
+        }
+        Ok(style)
+    }
+}
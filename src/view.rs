@@ -1,196 +1,822 @@
+use std::time::Duration;
+
 use clap::crate_version;
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{KeyEvent, MouseButton, MouseEvent, MouseEventKind};
 use ratatui::{
     Frame,
     layout::{
-        Constraint,
+        Constraint, Flex,
         HorizontalAlignment::{Center, Right},
-        Layout, Rect,
+        Layout, Position, Rect,
     },
-    style::{Color, Modifier, Style},
+    style::Style,
     symbols::line::VERTICAL,
     text::{Line, Span},
-    widgets::{Block, BorderType::Rounded, Gauge, List, ListItem, ListState, Paragraph, Tabs},
+    widgets::{
+        Block, BorderType::Rounded, Clear, Gauge, List, ListItem, ListState, Paragraph, Tabs, Wrap,
+    },
 };
+use ratatui_image::{StatefulImage, protocol::StatefulProtocol};
+use sonor::Track;
 
-use crate::{Action, Direction, ViewMode, sonos::SpeakerState};
+use crate::{
+    Action, Direction, Notification, NotificationLevel, ViewMode,
+    alarm_editor::{AlarmEditor, AlarmField},
+    alarms::Alarm,
+    filter::Filter,
+    keymap::{KeyAction, KeyContext, Keymap},
+    menu::{Menu, Prompt},
+    sonos::{ConnectError, FavoritePlaylist, PlaybackState, SpeakerState},
+    theme::Theme,
+};
 
-pub fn render_ui(frame: &mut Frame, state: &SpeakerState) {
-    let [title, tabs, playbar, view_tabs, content] = Layout::vertical([
+/// Split the screen into the title bar, group tabs, playbar, view tabs and content areas.
+fn main_layout(area: Rect) -> [Rect; 5] {
+    Layout::vertical([
         Constraint::Length(1),
         Constraint::Length(3),
         Constraint::Length(3),
         Constraint::Length(1),
         Constraint::Min(1),
     ])
-    .areas(frame.area());
+    .areas(area)
+}
+
+/// Split the title bar into the title and the volume readout.
+fn title_layout(area: Rect) -> [Rect; 2] {
+    Layout::horizontal([Constraint::Min(1), Constraint::Length(8)]).areas(area)
+}
+
+/// Split the inside of the playbar into the play/pause symbol and the progress gauge.
+fn playbar_layout(area: Rect) -> [Rect; 2] {
+    Layout::horizontal([Constraint::Length(3), Constraint::Min(1)]).areas(area)
+}
+
+/// `since_refresh` is how long ago the position of the current track was fetched, and is used
+/// to advance the progress bar while playing.
+pub fn render_ui(
+    frame: &mut Frame,
+    state: &SpeakerState,
+    since_refresh: Duration,
+    filter: &Filter,
+    album_art: Option<&mut StatefulProtocol>,
+    keymap: &Keymap,
+    theme: &Theme,
+) {
+    let [title, tabs, playbar, view_tabs, content] = main_layout(frame.area());
 
     // Title line
-    render_title_bar(state, frame, title);
+    render_title_bar(state, theme, frame, title);
 
     // Group tabs
-    render_tabs(state, frame, tabs);
+    render_tabs(state, theme, frame, tabs);
 
     // playbar
-    render_playbar(state, frame, playbar);
+    render_playbar(state, since_refresh, theme, frame, playbar);
 
     // View tabs
-    render_view_tabs(state, frame, view_tabs);
+    render_view_tabs(state, theme, frame, view_tabs);
+
+    // Album art next to the lists, roughly square and if there is enough room for it
+    let content = match album_art {
+        Some(image)
+            if matches!(state.current_view, ViewMode::Queue | ViewMode::Favorites)
+                && content.width >= 60
+                && content.height >= 6 =>
+        {
+            let width = (content.height * 2).min(content.width / 3);
+            let [content, art] =
+                Layout::horizontal([Constraint::Min(1), Constraint::Length(width)]).areas(content);
+            render_album_art(image, theme, frame, art);
+            content
+        }
+        _ => content,
+    };
 
     // Main content area (switches based on current view)
     match state.current_view {
-        ViewMode::Queue => render_queue(state, frame, content),
-        ViewMode::Favorites => render_favorites(state, frame, content),
+        ViewMode::Queue => render_queue(state, filter, keymap, theme, frame, content),
+        ViewMode::Favorites => render_favorites(state, filter, keymap, theme, frame, content),
+        ViewMode::Overview => render_overview(state, keymap, theme, frame, content),
+        ViewMode::Alarms => render_alarms(state, filter, keymap, theme, frame, content),
     }
 }
 
-pub fn handle_input(input: &KeyEvent, state: &SpeakerState) -> Action {
-    match input.code {
+/// `area` is the size of the screen, needed to move around the grid of the overview.
+pub fn handle_input(
+    input: &KeyEvent,
+    state: &SpeakerState,
+    filter: &Filter,
+    keymap: &Keymap,
+    area: Rect,
+    volume_step: i16,
+) -> Action {
+    let Some(action) = keymap.action_for(state.current_view.into(), input) else {
+        return Action::Nop;
+    };
+
+    match action {
         // View switching
-        KeyCode::Char('1') => Action::SwitchView(ViewMode::Queue),
-        KeyCode::Char('2') => Action::SwitchView(ViewMode::Favorites),
+        KeyAction::QueueView => Action::SwitchView(ViewMode::Queue),
+        KeyAction::FavoritesView => Action::SwitchView(ViewMode::Favorites),
+        KeyAction::OverviewView => Action::SwitchView(ViewMode::Overview),
+        KeyAction::AlarmsView => Action::SwitchView(ViewMode::Alarms),
 
-        // Favorites navigation (only when in Favorites view)
-        KeyCode::Up | KeyCode::Char('k') if matches!(state.current_view, ViewMode::Favorites) => {
-            Action::NavigateFavorites(Direction::Up)
+        // List and grid navigation
+        KeyAction::Up | KeyAction::Down | KeyAction::Left | KeyAction::Right => {
+            let direction = match action {
+                KeyAction::Up => Direction::Up,
+                KeyAction::Down => Direction::Down,
+                KeyAction::Left => Direction::Left,
+                _ => Direction::Right,
+            };
+            if state.current_view == ViewMode::Overview {
+                let [_, _, _, _, content] = main_layout(area);
+                navigate_overview(state, content, direction)
+            } else {
+                navigate(state, filter, direction)
+            }
         }
-        KeyCode::Down | KeyCode::Char('j') if matches!(state.current_view, ViewMode::Favorites) => {
-            Action::NavigateFavorites(Direction::Down)
+        KeyAction::PlaySelected => {
+            let selection = visible_selection(state, filter);
+            match state.current_view {
+                ViewMode::Queue => selection.map_or(Action::Nop, Action::PlayQueueItem),
+                ViewMode::Favorites => selection.map_or(Action::Nop, Action::PlayFavorite),
+                // The selected card is the selected group
+                ViewMode::Overview => play_pause(state),
+                ViewMode::Alarms => Action::Nop,
+            }
         }
-        KeyCode::Enter if matches!(state.current_view, ViewMode::Favorites) => {
-            Action::PlayFavorite(state.selected_favorite)
+
+        // Alarms
+        KeyAction::Toggle if state.current_view == ViewMode::Alarms => {
+            visible_selection(state, filter).map_or(Action::Nop, Action::ToggleAlarm)
         }
 
         // Playback controls (work in any view)
-        KeyCode::Char(' ') => {
-            if state.is_playing {
-                Action::Pause
+        KeyAction::PlayPause => play_pause(state),
+        KeyAction::Next => Action::Next,
+        KeyAction::Previous => Action::Prev,
+        KeyAction::VolumeDown => Action::VolAdjust(-volume_step),
+        KeyAction::VolumeUp => Action::VolAdjust(volume_step),
+
+        // Group switching
+        KeyAction::NextGroup => Action::CycleGroup(1),
+        KeyAction::PreviousGroup => Action::CycleGroup(-1),
+
+        // Quitting, retrying, the help, the filter, the menus and the alarm editor are handled by
+        // the main loop
+        KeyAction::Quit
+        | KeyAction::Retry
+        | KeyAction::Help
+        | KeyAction::Filter
+        | KeyAction::Inputs
+        | KeyAction::PlayUrl
+        | KeyAction::EnqueueUrl
+        | KeyAction::Scenes
+        | KeyAction::MovePlayback
+        | KeyAction::CopyPlayback
+        | KeyAction::New
+        | KeyAction::Edit
+        | KeyAction::Toggle
+        // Deleting is confirmed first
+        | KeyAction::Delete
+        | KeyAction::Decrease
+        | KeyAction::Increase
+        | KeyAction::Save
+        | KeyAction::Cancel => Action::Nop,
+    }
+}
+
+fn play_pause(state: &SpeakerState) -> Action {
+    if state.is_playing {
+        Action::Pause
+    } else {
+        Action::Play
+    }
+}
+
+/// Map clicks and scrolling to actions, depending on what's under the mouse.
+pub fn handle_mouse(
+    event: &MouseEvent,
+    state: &SpeakerState,
+    filter: &Filter,
+    area: Rect,
+    volume_step: i16,
+) -> Action {
+    let [title, tabs, playbar, view_tabs, content] = main_layout(area);
+    let [_, volume] = title_layout(title);
+    let [_, gauge] = playbar_layout(Block::bordered().inner(playbar));
+    let position = Position::new(event.column, event.row);
+
+    match event.kind {
+        MouseEventKind::Down(MouseButton::Left) => {
+            if tabs.contains(position) {
+                let titles = group_tab_titles(state);
+                let titles = titles.iter().map(String::as_str);
+                tab_at(titles, Block::bordered().inner(tabs), position)
+                    .map_or(Action::Nop, Action::SelectGroup)
+            } else if view_tabs.contains(position) {
+                match tab_at(VIEW_NAMES.iter().copied(), view_tabs, position) {
+                    Some(0) => Action::SwitchView(ViewMode::Queue),
+                    Some(1) => Action::SwitchView(ViewMode::Favorites),
+                    Some(2) => Action::SwitchView(ViewMode::Overview),
+                    Some(3) => Action::SwitchView(ViewMode::Alarms),
+                    _ => Action::Nop,
+                }
+            } else if gauge.contains(position) {
+                match &state.now_playing {
+                    Some(track) if track.duration() > 0 => {
+                        let ratio = f64::from(position.x - gauge.x) / f64::from(gauge.width);
+                        Action::Seek((ratio * f64::from(track.duration())) as u32)
+                    }
+                    _ => Action::Nop,
+                }
+            } else if state.current_view == ViewMode::Overview && content.contains(position) {
+                overview_cards(state, content)
+                    .into_iter()
+                    .find(|(_, card)| card.contains(position))
+                    .map_or(Action::Nop, |(i, _)| Action::SelectGroup(i))
             } else {
-                Action::Play
+                Action::Nop
             }
         }
-        KeyCode::Char('n') => Action::Next,
-        KeyCode::Char('p') => Action::Prev,
-        KeyCode::Char('[') => Action::VolAdjust(-2),
-        KeyCode::Char(']') => Action::VolAdjust(2),
-
-        // Group switching
-        KeyCode::Tab => {
-            if input.modifiers.contains(KeyModifiers::SHIFT) {
-                Action::PrevSpeaker
+        // Right-clicking on another group moves the playback there
+        MouseEventKind::Down(MouseButton::Right) if tabs.contains(position) => {
+            let titles = group_tab_titles(state);
+            let titles = titles.iter().map(String::as_str);
+            match tab_at(titles, Block::bordered().inner(tabs), position) {
+                Some(to) if to != state.selected_group => Action::TransferPlayback {
+                    to,
+                    stop_source: true,
+                },
+                _ => Action::Nop,
+            }
+        }
+        MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+            let up = event.kind == MouseEventKind::ScrollUp;
+            if volume.contains(position) {
+                Action::VolAdjust(if up { volume_step } else { -volume_step })
+            } else if state.current_view == ViewMode::Overview && content.contains(position) {
+                let direction = if up {
+                    Direction::Left
+                } else {
+                    Direction::Right
+                };
+                navigate_overview(state, content, direction)
+            } else if content.contains(position) {
+                navigate(
+                    state,
+                    filter,
+                    if up { Direction::Up } else { Direction::Down },
+                )
             } else {
-                Action::NextSpeaker
+                Action::Nop
             }
         }
-
         _ => Action::Nop,
     }
 }
 
-fn render_title_bar(state: &SpeakerState, frame: &mut Frame, area: Rect) {
-    let [title_area, volume_area] =
-        Layout::horizontal([Constraint::Min(1), Constraint::Length(8)]).areas(area);
+/// Move the selection of the list in the current view, skipping the items hidden by the filter.
+fn navigate(state: &SpeakerState, filter: &Filter, direction: Direction) -> Action {
+    if !filter_applies(state, filter) {
+        return match state.current_view {
+            ViewMode::Queue => Action::NavigateQueue(direction.step()),
+            ViewMode::Favorites => Action::NavigateFavorites(direction.step()),
+            ViewMode::Overview => Action::Nop,
+            ViewMode::Alarms => Action::NavigateAlarms(direction.step()),
+        };
+    }
+
+    let visible = visible_items(state, filter);
+    let current = match state.current_view {
+        ViewMode::Queue => queue_selection(state),
+        ViewMode::Favorites => Some(state.selected_favorite),
+        ViewMode::Overview => Some(state.selected_group),
+        ViewMode::Alarms => Some(state.selected_alarm),
+    };
+    let target = match (direction, current) {
+        (_, None) => visible.first(),
+        (Direction::Up | Direction::Left, Some(current)) => {
+            visible.iter().rev().find(|i| **i < current)
+        }
+        (Direction::Down | Direction::Right, Some(current)) => {
+            visible.iter().find(|i| **i > current)
+        }
+    };
+    target.map_or(Action::Nop, |i| select_item(state, *i))
+}
+
+/// Select the first item matching the filter, e.g. after the query changed.
+pub fn select_first_match(state: &SpeakerState, filter: &Filter) -> Action {
+    visible_items(state, filter)
+        .first()
+        .map_or(Action::Nop, |i| select_item(state, *i))
+}
+
+fn select_item(state: &SpeakerState, index: usize) -> Action {
+    match state.current_view {
+        ViewMode::Queue => Action::SelectQueueItem(index),
+        ViewMode::Favorites => Action::SelectFavorite(index),
+        ViewMode::Overview => Action::SelectGroup(index),
+        ViewMode::Alarms => Action::SelectAlarm(index),
+    }
+}
+
+fn filter_applies(state: &SpeakerState, filter: &Filter) -> bool {
+    filter.is_active() && filter.view == state.current_view
+}
+
+/// The selected item of the current view's list, unless the filter hides it.
+pub fn visible_selection(state: &SpeakerState, filter: &Filter) -> Option<usize> {
+    let selected = match state.current_view {
+        ViewMode::Queue => queue_selection(state)?,
+        ViewMode::Favorites => state.selected_favorite,
+        ViewMode::Overview => state.selected_group,
+        ViewMode::Alarms => state.selected_alarm,
+    };
+    // Also rules out empty lists
+    visible_items(state, filter)
+        .contains(&selected)
+        .then_some(selected)
+}
+
+/// Indices of the items of the current view's list that match the filter.
+fn visible_items(state: &SpeakerState, filter: &Filter) -> Vec<usize> {
+    let texts: Vec<String> = match state.current_view {
+        ViewMode::Queue => state.queue.iter().map(queue_text).collect(),
+        ViewMode::Favorites => state.favorites.iter().map(favorite_text).collect(),
+        ViewMode::Overview => state.group_names.clone(),
+        ViewMode::Alarms => state
+            .alarms
+            .iter()
+            .map(|alarm| alarm_text(state, alarm))
+            .collect(),
+    };
+    texts
+        .iter()
+        .enumerate()
+        .filter(|(_, text)| !filter_applies(state, filter) || filter.matches(text).is_some())
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Text of a track in the queue, which is also what the filter matches against
+fn queue_text(track: &Track) -> String {
+    format!(
+        "{} - {} - {}",
+        track.creator().unwrap_or("Unknown"),
+        track.album().unwrap_or("Unknown"),
+        track.title()
+    )
+}
+
+fn favorite_text(favorite: &FavoritePlaylist) -> String {
+    format!("{} - {}", favorite.title, favorite.description)
+}
+
+fn alarm_text(state: &SpeakerState, alarm: &Alarm) -> String {
+    let (hours, minutes) = alarm.time();
+    let room = state
+        .rooms
+        .iter()
+        .find(|r| r.uuid == alarm.room_uuid)
+        .map_or(alarm.room_uuid.as_str(), |r| r.name.as_str());
+    format!(
+        "{hours:02}:{minutes:02}  {:<16}  {room:<16}  {}",
+        alarm.recurrence_label(),
+        alarm.source_label()
+    )
+}
+
+/// Split a text into spans, with the characters at the given positions highlighted.
+fn highlight_matches(text: String, positions: &[usize], style: Style) -> Vec<Span<'static>> {
+    if positions.is_empty() {
+        return vec![Span::raw(text)];
+    }
+    let mut spans = vec![];
+    let mut current = String::new();
+    let mut matching = false;
+    for (i, c) in text.chars().enumerate() {
+        let is_match = positions.contains(&i);
+        if is_match != matching && !current.is_empty() {
+            let part = std::mem::take(&mut current);
+            spans.push(if matching {
+                Span::styled(part, style)
+            } else {
+                Span::raw(part)
+            });
+        }
+        matching = is_match;
+        current.push(c);
+    }
+    spans.push(if matching {
+        Span::styled(current, style)
+    } else {
+        Span::raw(current)
+    });
+    spans
+}
+
+/// The query of the filter, shown in the top right corner of the list.
+fn filter_title(state: &SpeakerState, filter: &Filter, theme: &Theme) -> Line<'static> {
+    if !filter_applies(state, filter) {
+        return Line::default();
+    }
+    let cursor = if filter.editing { "▏" } else { "" };
+    Line::from(vec![
+        Span::raw(" /"),
+        Span::styled(filter.query.clone(), theme.filter_match),
+        Span::raw(format!("{cursor} ")),
+    ])
+    .right_aligned()
+}
+
+/// Index of the tab under the given position, laid out the way `Tabs` renders them: each title
+/// surrounded by one space of padding, and separated by a one column divider.
+fn tab_at<'a>(
+    titles: impl Iterator<Item = &'a str>,
+    area: Rect,
+    position: Position,
+) -> Option<usize> {
+    let mut x = area.x;
+    for (i, title) in titles.enumerate() {
+        let width = Line::from(title).width() as u16 + 2;
+        if (x..x + width).contains(&position.x) {
+            return Some(i);
+        }
+        x += width + 1;
+    }
+    None
+}
+
+/// The selected track in the queue: the one picked by the user, or else the one playing.
+fn queue_selection(state: &SpeakerState) -> Option<usize> {
+    state.selected_queue_item.or_else(|| {
+        let playing = state.now_playing.as_ref()?;
+        state
+            .queue
+            .iter()
+            .position(|t| t.uri() == playing.track().uri())
+    })
+}
+
+const SPINNER: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// Screen displayed while looking for speakers.
+pub fn render_connecting(frame: &mut Frame, discovered: &[String], tick: usize, theme: &Theme) {
+    let mut lines = vec![
+        Line::from(format!(
+            "{} Looking for Sonos speakers...",
+            SPINNER[tick % SPINNER.len()]
+        )),
+        Line::default(),
+    ];
+    if discovered.is_empty() {
+        lines.push(Line::styled("No speakers found yet", theme.hint));
+    } else {
+        lines.extend(
+            discovered
+                .iter()
+                .map(|name| Line::from(format!("✓ {name}"))),
+        );
+    }
+
+    let area = centered_rect(frame.area(), 50, lines.len() as u16 + 2);
+    let block = Block::bordered()
+        .border_type(Rounded)
+        .border_style(theme.border)
+        .title(Span::styled(
+            format!(" Sinuous {} ", crate_version!()),
+            theme.title,
+        ));
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Screen displayed when we couldn't connect to the speakers.
+pub fn render_failed(frame: &mut Frame, error: &ConnectError, keymap: &Keymap, theme: &Theme) {
+    let hints = keymap.hints(
+        KeyContext::Global,
+        &[(&[KeyAction::Retry], "retry"), (&[KeyAction::Quit], "quit")],
+    );
+    let text = Paragraph::new(error.to_string())
+        .wrap(Wrap { trim: true })
+        .block(
+            Block::bordered()
+                .border_type(Rounded)
+                .border_style(theme.border)
+                .title(" Could not connect ")
+                .title_bottom(Line::from(hints).centered().style(theme.hint)),
+        );
+
+    let area = centered_rect(frame.area(), 60, 7);
+    frame.render_widget(text, area);
+}
+
+/// Popup listing all the key bindings, grouped by context, scrolled down by `scroll` lines when
+/// they don't all fit on screen. Returns the scroll actually used, which stops at the last line.
+pub fn render_help(
+    frame: &mut Frame,
+    keymap: &Keymap,
+    theme: &Theme,
+    context: KeyContext,
+    scroll: u16,
+) -> u16 {
+    let mut lines = vec![];
+    for &context in KeyContext::ALL {
+        let bindings: Vec<_> = keymap.bindings(context).collect();
+        if bindings.is_empty() {
+            continue;
+        }
+        if !lines.is_empty() {
+            lines.push(Line::default());
+        }
+        let title = match context {
+            KeyContext::Global => "Everywhere",
+            KeyContext::Queue => "Queue view",
+            KeyContext::Favorites => "Favorites view",
+            KeyContext::Overview => "Overview",
+            KeyContext::Alarms => "Alarms view",
+            KeyContext::AlarmEditor => "Alarm editor",
+        };
+        lines.push(Line::styled(title, theme.view_tab));
+        for (action, chords) in bindings {
+            let keys = chords
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" / ");
+            lines.push(Line::from(vec![
+                Span::raw(format!("  {keys:<16} ")),
+                Span::raw(action.description()),
+            ]));
+        }
+    }
+
+    let total = lines.len() as u16;
+    let area = centered_rect(frame.area(), 50, total + 2);
+    let visible = area.height.saturating_sub(2);
+    let scroll = scroll.min(total.saturating_sub(visible));
+
+    let mut block = Block::bordered()
+        .border_type(Rounded)
+        .border_style(theme.border)
+        .title(" Key bindings ");
+    let hints = if visible < total {
+        block = block.title_top(
+            Line::from(format!(
+                " {}-{} of {total} ",
+                scroll + 1,
+                (scroll + visible).min(total)
+            ))
+            .right_aligned(),
+        );
+        keymap.hints(
+            context,
+            &[
+                (&[KeyAction::Up, KeyAction::Down], "scroll"),
+                (&[KeyAction::Help], "close"),
+            ],
+        )
+    } else {
+        keymap.hints(context, &[(&[KeyAction::Help], "close")])
+    };
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines)
+            .scroll((scroll, 0))
+            .block(block.title_bottom(Line::from(hints).centered().style(theme.hint))),
+        area,
+    );
+    scroll
+}
+
+/// Toasts stacked in the bottom right corner, most recent at the bottom.
+pub fn render_notifications<'a>(
+    frame: &mut Frame,
+    notifications: impl DoubleEndedIterator<Item = &'a Notification>,
+    theme: &Theme,
+) {
+    let area = frame.area();
+    let width = (area.width / 2).clamp(20.min(area.width), 60);
+    // Leave the bottom border of the content block visible
+    let mut bottom = area.bottom().saturating_sub(1);
+
+    for notification in notifications.rev() {
+        let (title, style) = match notification.level {
+            NotificationLevel::Info => (" Info ", theme.notification_info),
+            NotificationLevel::Error => (" Error ", theme.notification_error),
+        };
+        let text = Paragraph::new(notification.message.as_str())
+            .wrap(Wrap { trim: true })
+            .block(
+                Block::bordered()
+                    .border_type(Rounded)
+                    .border_style(style)
+                    .title(title),
+            );
+        // Rough estimate of the wrapped height, capped to a few lines
+        let inner = width.saturating_sub(2).max(1) as usize;
+        let lines = notification.message.chars().count().div_ceil(inner).max(1);
+        let height = (lines as u16 + 2).min(6);
+        if bottom < area.top() + height {
+            break;
+        }
+        let toast = Rect::new(area.right() - width, bottom - height, width, height);
+        frame.render_widget(Clear, toast);
+        frame.render_widget(text, toast);
+        bottom -= height;
+    }
+}
+
+/// A rectangle of the given size (or smaller, if it doesn't fit) in the middle of `area`.
+fn centered_rect(area: Rect, width: u16, height: u16) -> Rect {
+    let [area] = Layout::horizontal([Constraint::Length(width.min(area.width))])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::vertical([Constraint::Length(height.min(area.height))])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
+
+fn render_title_bar(state: &SpeakerState, theme: &Theme, frame: &mut Frame, area: Rect) {
+    let [title_area, volume_area] = title_layout(area);
 
     let header = vec![Line::from(vec![
-        Span::styled(
-            format!("Sinuous {}", crate_version!()),
-            Style::default()
-                .fg(Color::Yellow)
-                .bg(Color::DarkGray)
-                .add_modifier(Modifier::BOLD),
-        ),
-        Span::styled(" -- Playing on ", Style::default()),
-        Span::styled(state.group_name(), Style::default().fg(Color::Green)),
+        Span::styled(format!("Sinuous {}", crate_version!()), theme.title),
+        Span::raw(" -- Playing on "),
+        Span::styled(state.group_name(), theme.group),
     ])];
     let title = Paragraph::new(header);
     frame.render_widget(title, title_area);
 
-    let vol_text = format!("🔊: {:2} ", state.current_volume);
+    // Until the speakers confirm the change
+    let pending = if state.pending.volume { "…" } else { " " };
+    let vol_text = Line::from(vec![
+        Span::raw(format!("🔊: {:2}", state.current_volume)),
+        Span::styled(pending, theme.hint),
+    ]);
     let vol = Paragraph::new(vol_text).alignment(Right);
     frame.render_widget(vol, volume_area);
 }
 
-fn render_tabs(state: &SpeakerState, frame: &mut Frame, area: Rect) {
-    let tabs = Tabs::new(state.group_names.iter().cloned())
-        .block(Block::bordered().border_type(Rounded).title(" Groups "))
-        .highlight_style(Style::default().fg(Color::Green))
+/// Name of each group, preceded by whether it's playing, and followed by whether it's muted.
+fn group_tab_titles(state: &SpeakerState) -> Vec<String> {
+    state
+        .group_names
+        .iter()
+        .enumerate()
+        .map(
+            |(i, name)| match state.group_indicators.get(i).copied().flatten() {
+                Some(indicator) => {
+                    let icon = match indicator.state {
+                        PlaybackState::Playing => "⏵",
+                        PlaybackState::Paused => "⏸",
+                        PlaybackState::Stopped => "⏹",
+                    };
+                    let muted = if indicator.muted { " 🔇" } else { "" };
+                    format!("{icon} {name}{muted}")
+                }
+                None => name.clone(),
+            },
+        )
+        .collect()
+}
+
+fn render_tabs(state: &SpeakerState, theme: &Theme, frame: &mut Frame, area: Rect) {
+    let tabs = Tabs::new(group_tab_titles(state))
+        .block(
+            Block::bordered()
+                .border_type(Rounded)
+                .border_style(theme.border)
+                .title(" Groups "),
+        )
+        .highlight_style(theme.group_tab)
         .select(state.selected_group)
         .divider(VERTICAL);
 
     frame.render_widget(tabs, area);
 }
 
-fn render_view_tabs(state: &SpeakerState, frame: &mut Frame, area: Rect) {
-    let view_names = vec!["1 Queue", "2 Favorites"];
+const VIEW_NAMES: &[&str] = &["1 Queue", "2 Favorites", "3 Overview", "4 Alarms"];
+
+fn render_view_tabs(state: &SpeakerState, theme: &Theme, frame: &mut Frame, area: Rect) {
     let selected = match state.current_view {
         ViewMode::Queue => 0,
         ViewMode::Favorites => 1,
+        ViewMode::Overview => 2,
+        ViewMode::Alarms => 3,
     };
 
-    let tabs = Tabs::new(view_names)
-        .highlight_style(
-            Style::default()
-                .fg(Color::Cyan)
-                .add_modifier(Modifier::BOLD),
-        )
+    let tabs = Tabs::new(VIEW_NAMES.to_vec())
+        .highlight_style(theme.view_tab)
         .select(selected)
         .divider(VERTICAL);
 
     frame.render_widget(tabs, area);
 }
 
-fn render_queue(state: &SpeakerState, frame: &mut Frame, area: Rect) {
-    // Select the currently playing track in the queue (if any)
+fn render_queue(
+    state: &SpeakerState,
+    filter: &Filter,
+    keymap: &Keymap,
+    theme: &Theme,
+    frame: &mut Frame,
+    area: Rect,
+) {
+    // Select the track picked by the user, or the one currently playing (if any)
+    let visible = visible_items(state, filter);
     let mut list_state = ListState::default();
-    let selection = state.now_playing.as_ref().and_then(|track| {
+    list_state.select(queue_selection(state).and_then(|s| visible.iter().position(|i| *i == s)));
+    let playing = state.now_playing.as_ref().and_then(|track| {
         state
             .queue
             .iter()
             .position(|t| t.uri() == track.track().uri())
     });
-    list_state.select(selection);
-
-    let items = state.queue.iter().map(|t| {
-        let s = format!(
-            "{} - {} - {} ({})",
-            t.creator().unwrap_or("Unknown"),
-            t.album().unwrap_or("Unknown"),
-            t.title(),
+
+    let hints = keymap.hints(
+        KeyContext::Queue,
+        &[
+            (&[KeyAction::PlayPause], "play/pause"),
+            (&[KeyAction::Next], "next"),
+            (&[KeyAction::Previous], "prev"),
+            (&[KeyAction::VolumeDown, KeyAction::VolumeUp], "volume"),
+            (&[KeyAction::Filter], "filter"),
+            (&[KeyAction::Help], "help"),
+        ],
+    );
+
+    let items = visible.iter().map(|&i| {
+        let t = &state.queue[i];
+        let text = queue_text(t);
+        let positions = filter.matches(&text).unwrap_or_default();
+        let mut spans = vec![Span::raw(if Some(i) == playing { "⏵" } else { " " })];
+        spans.extend(highlight_matches(text, &positions, theme.filter_match));
+        spans.push(Span::raw(format!(
+            " ({})",
             format_duration(t.duration().unwrap_or(0))
-        );
-        ListItem::new(s)
+        )));
+        let item = ListItem::new(Line::from(spans));
+        if Some(i) == playing {
+            item.style(theme.queue_highlight)
+        } else {
+            item
+        }
     });
     let list = List::new(items)
-        .highlight_style(Style::default().fg(Color::LightMagenta))
-        .highlight_symbol("⏵")
+        .highlight_style(theme.queue_selection)
         .block(
             Block::bordered()
                 .title_top(" Queue ")
-                .title_bottom(
-                    Line::from(" SPACE play/pause • n next • p prev • [ ] volume ")
-                        .centered()
-                        .style(Style::default().fg(Color::DarkGray)),
-                )
-                .border_type(Rounded),
+                .title_top(filter_title(state, filter, theme))
+                .title_bottom(Line::from(hints).centered().style(theme.hint))
+                .border_type(Rounded)
+                .border_style(theme.border),
         );
 
     frame.render_stateful_widget(list, area, &mut list_state);
 }
 
-fn render_playbar(state: &SpeakerState, frame: &mut Frame, area: Rect) {
-    let (np, label, ratio) = if let Some(track) = &state.now_playing {
+fn render_playbar(
+    state: &SpeakerState,
+    since_refresh: Duration,
+    theme: &Theme,
+    frame: &mut Frame,
+    area: Rect,
+) {
+    // Live streams have no progress to show: they get what's on air instead of the gauge
+    enum Progress<'a> {
+        Gauge(String, f64),
+        Live(Vec<&'a str>),
+    }
+
+    let (np, progress) = if let Some(input) = &state.playing_input {
+        (format!(" Input: {input} "), Progress::Live(vec!["Live"]))
+    } else if let Some(radio) = &state.radio {
+        let mut details = vec!["Live"];
+        details.extend(radio.song.as_deref());
+        details.extend(radio.show.as_deref());
+        (format!(" {} ", radio.station), Progress::Live(details))
+    } else if let Some(track) = &state.now_playing {
+        // Interpolate the position since it was last fetched
+        let mut elapsed = f64::from(track.elapsed());
+        if state.is_playing {
+            elapsed += since_refresh.as_secs_f64();
+        }
+        if track.duration() != 0 {
+            elapsed = elapsed.min(f64::from(track.duration()));
+        }
         let percent = if track.duration() != 0 {
-            f64::clamp(
-                f64::from(track.elapsed()) / f64::from(track.duration()),
-                0.0,
-                1.0,
-            )
+            f64::clamp(elapsed / f64::from(track.duration()), 0.0, 1.0)
         } else {
             0.0
         };
         let label = format!(
             "{} / {}",
-            format_duration(track.elapsed()),
+            format_duration(elapsed as u32),
             format_duration(track.duration())
         );
         let title = format!(
@@ -199,74 +825,420 @@ fn render_playbar(state: &SpeakerState, frame: &mut Frame, area: Rect) {
             track.track().album().unwrap_or("Unknown"),
             track.track().title()
         );
-        (title, label, percent)
+        (title, Progress::Gauge(label, percent))
     } else {
         (
             " Nothing currently playing ".to_owned(),
-            "0:00 / 0:00".to_owned(),
-            0.0,
+            Progress::Gauge("0:00 / 0:00".to_owned(), 0.0),
         )
     };
 
     // Border around the whole playbar section
-    let block = Block::bordered().border_type(Rounded).title(np);
+    let block = Block::bordered()
+        .border_type(Rounded)
+        .border_style(theme.border)
+        .title(np);
     // The inner area is where the gauge and control buttons will be rendered
     let playbar_area = block.inner(area);
 
     // split the inner area into 2 columns for the buttons and the gauge
-    let [symbol_area, bar_area] =
-        Layout::horizontal([Constraint::Length(3), Constraint::Min(1)]).areas(playbar_area);
+    let [symbol_area, bar_area] = playbar_layout(playbar_area);
 
     let media_symbol = if state.is_playing { "⏵" } else { "⏸" };
-    let symbol = Paragraph::new(media_symbol).alignment(Center);
-
-    let playbar = Gauge::default()
-        .use_unicode(true)
-        .gauge_style(
-            Style::default()
-                .fg(Color::LightGreen)
-                .bg(Color::Black)
-                .add_modifier(Modifier::ITALIC),
-        )
-        .label(label)
-        .ratio(ratio);
+    // Dimmed until the speakers confirm the change
+    let symbol_style = if state.pending.playing {
+        theme.hint
+    } else {
+        Style::default()
+    };
+    let symbol = Paragraph::new(Span::styled(media_symbol, symbol_style)).alignment(Center);
 
     // render all the widgets
     frame.render_widget(block, area);
     frame.render_widget(symbol, symbol_area);
-    frame.render_widget(playbar, bar_area);
+    match progress {
+        Progress::Gauge(label, ratio) => {
+            let playbar = Gauge::default()
+                .use_unicode(true)
+                .gauge_style(theme.gauge)
+                .label(label)
+                .ratio(ratio);
+            frame.render_widget(playbar, bar_area);
+        }
+        Progress::Live(details) => {
+            let mut spans = vec![Span::styled("● ", theme.gauge)];
+            spans.push(Span::raw(details.join(" • ")));
+            frame.render_widget(Paragraph::new(Line::from(spans)), bar_area);
+        }
+    }
 }
 
-fn render_favorites(state: &SpeakerState, frame: &mut Frame, area: Rect) {
+/// Minimum size of the cards of the overview
+const CARD_WIDTH: u16 = 34;
+const CARD_HEIGHT: u16 = 6;
+
+/// The cards of the overview that fit on screen, and where to draw them. They're laid out in a
+/// grid, scrolled so that the selected group is visible.
+fn overview_cards(state: &SpeakerState, area: Rect) -> Vec<(usize, Rect)> {
+    let inner = Block::bordered().inner(area);
+    let columns = (inner.width / CARD_WIDTH).max(1);
+    let rows = (inner.height / CARD_HEIGHT).max(1) as usize;
+    let width = inner.width / columns;
+    let columns = columns as usize;
+    let first_row = (state.selected_group / columns + 1).saturating_sub(rows);
+
+    (0..state.group_names.len())
+        .filter_map(|i| {
+            let (row, column) = (i / columns, i % columns);
+            if row < first_row || row >= first_row + rows {
+                return None;
+            }
+            let x = inner.x + column as u16 * width;
+            let y = inner.y + (row - first_row) as u16 * CARD_HEIGHT;
+            let card = Rect::new(x, y, width, CARD_HEIGHT).intersection(inner);
+            Some((i, card))
+        })
+        .collect()
+}
+
+/// Move the selection around the grid of the overview.
+fn navigate_overview(state: &SpeakerState, area: Rect, direction: Direction) -> Action {
+    let columns = (Block::bordered().inner(area).width / CARD_WIDTH).max(1) as usize;
+    let current = state.selected_group;
+    let target = match direction {
+        Direction::Up => current.checked_sub(columns),
+        Direction::Down => Some(current + columns),
+        Direction::Left => current.checked_sub(1),
+        Direction::Right => Some(current + 1),
+    };
+    match target {
+        Some(target) if target < state.group_names.len() => Action::SelectGroup(target),
+        _ => Action::Nop,
+    }
+}
+
+fn render_overview(
+    state: &SpeakerState,
+    keymap: &Keymap,
+    theme: &Theme,
+    frame: &mut Frame,
+    area: Rect,
+) {
+    let hints = keymap.hints(
+        KeyContext::Overview,
+        &[
+            (
+                &[
+                    KeyAction::Left,
+                    KeyAction::Down,
+                    KeyAction::Up,
+                    KeyAction::Right,
+                ],
+                "select",
+            ),
+            (&[KeyAction::PlaySelected], "play/pause"),
+            (&[KeyAction::VolumeDown, KeyAction::VolumeUp], "volume"),
+            (&[KeyAction::Help], "help"),
+        ],
+    );
+    frame.render_widget(
+        Block::bordered()
+            .title_top(" Overview ")
+            .title_bottom(Line::from(hints).centered().style(theme.hint))
+            .border_type(Rounded)
+            .border_style(theme.border),
+        area,
+    );
+
+    for (i, card) in overview_cards(state, area) {
+        let selected = i == state.selected_group;
+        let lines = match state.group_statuses.get(i).and_then(Option::as_ref) {
+            Some(status) => {
+                let playing = if status.playing {
+                    "⏵ Playing"
+                } else {
+                    "⏸ Paused"
+                };
+                let (title, details) = match &status.title {
+                    Some(title) => (
+                        Line::from(title.as_str()),
+                        Line::styled(
+                            format!(
+                                "{} - {}",
+                                status.artist.as_deref().unwrap_or("Unknown"),
+                                status.album.as_deref().unwrap_or("Unknown")
+                            ),
+                            theme.hint,
+                        ),
+                    ),
+                    None => (Line::styled("Nothing playing", theme.hint), Line::default()),
+                };
+                let muted = state
+                    .group_indicators
+                    .get(i)
+                    .copied()
+                    .flatten()
+                    .is_some_and(|indicator| indicator.muted);
+                let volume = if muted { "🔇" } else { "🔊" };
+                vec![
+                    Line::from(format!("{playing}  {volume} {}", status.volume)),
+                    title,
+                    details,
+                    Line::styled(
+                        format!(
+                            "{} / {} • {} tracks",
+                            format_duration(status.elapsed),
+                            format_duration(status.duration),
+                            status.queue_length
+                        ),
+                        theme.hint,
+                    ),
+                ]
+            }
+            None => vec![Line::styled("Loading...", theme.hint)],
+        };
+
+        let name = Span::styled(
+            format!(" {} ", state.group_names[i]),
+            if selected {
+                theme.group_tab
+            } else {
+                theme.group
+            },
+        );
+        let block = Block::bordered()
+            .border_type(Rounded)
+            .border_style(if selected {
+                theme.group_tab
+            } else {
+                theme.border
+            })
+            .title(name);
+        frame.render_widget(Paragraph::new(lines).block(block), card);
+    }
+}
+
+fn render_album_art(image: &mut StatefulProtocol, theme: &Theme, frame: &mut Frame, area: Rect) {
+    let block = Block::bordered()
+        .border_type(Rounded)
+        .border_style(theme.border);
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    frame.render_stateful_widget(StatefulImage::default(), inner, image);
+}
+
+fn render_favorites(
+    state: &SpeakerState,
+    filter: &Filter,
+    keymap: &Keymap,
+    theme: &Theme,
+    frame: &mut Frame,
+    area: Rect,
+) {
+    let visible = visible_items(state, filter);
     let mut list_state = ListState::default();
-    list_state.select(Some(state.selected_favorite));
+    list_state.select(visible.iter().position(|i| *i == state.selected_favorite));
+
+    let hints = keymap.hints(
+        KeyContext::Favorites,
+        &[
+            (&[KeyAction::Up, KeyAction::Down], "navigate"),
+            (&[KeyAction::PlaySelected], "play"),
+            (&[KeyAction::Filter], "filter"),
+            (&[KeyAction::Help], "help"),
+        ],
+    );
 
-    let items = state.favorites.iter().map(|fav| {
-        let s = format!("{} - {}", fav.title, fav.description);
-        ListItem::new(s)
+    let items = visible.iter().map(|&i| {
+        let text = favorite_text(&state.favorites[i]);
+        let positions = filter.matches(&text).unwrap_or_default();
+        ListItem::new(Line::from(highlight_matches(
+            text,
+            &positions,
+            theme.filter_match,
+        )))
     });
 
     let list = List::new(items)
-        .highlight_style(
-            Style::default()
-                .fg(Color::Cyan)
-                .add_modifier(Modifier::BOLD),
-        )
-        .highlight_symbol("⏵ ")
+        .highlight_style(theme.favorite_highlight)
+        // Until the speakers confirm the selection
+        .highlight_symbol(if state.pending.favorite {
+            "… "
+        } else {
+            "⏵ "
+        })
         .block(
             Block::bordered()
                 .title_top(" Favorite Playlists ")
+                .title_top(filter_title(state, filter, theme))
+                .title_bottom(Line::from(hints).centered().style(theme.hint))
+                .border_type(Rounded)
+                .border_style(theme.border),
+        );
+
+    frame.render_stateful_widget(list, area, &mut list_state);
+}
+
+fn render_alarms(
+    state: &SpeakerState,
+    filter: &Filter,
+    keymap: &Keymap,
+    theme: &Theme,
+    frame: &mut Frame,
+    area: Rect,
+) {
+    let visible = visible_items(state, filter);
+    let mut list_state = ListState::default();
+    list_state.select(visible.iter().position(|i| *i == state.selected_alarm));
+
+    let hints = keymap.hints(
+        KeyContext::Alarms,
+        &[
+            (&[KeyAction::Toggle], "on/off"),
+            (&[KeyAction::Edit], "edit"),
+            (&[KeyAction::New], "new"),
+            (&[KeyAction::Delete], "delete"),
+            (&[KeyAction::Help], "help"),
+        ],
+    );
+
+    let items = visible.iter().map(|&i| {
+        let alarm = &state.alarms[i];
+        let text = alarm_text(state, alarm);
+        let positions = filter.matches(&text).unwrap_or_default();
+        let mut spans = vec![Span::raw(if alarm.enabled { "● " } else { "○ " })];
+        spans.extend(highlight_matches(text, &positions, theme.filter_match));
+        spans.push(Span::raw(format!("  🔊 {}", alarm.volume)));
+        let item = ListItem::new(Line::from(spans));
+        if alarm.enabled {
+            item
+        } else {
+            item.style(theme.hint)
+        }
+    });
+
+    let list = List::new(items)
+        .highlight_style(theme.queue_selection)
+        .block(
+            Block::bordered()
+                .title_top(" Alarms ")
+                .title_top(filter_title(state, filter, theme))
+                .title_bottom(Line::from(hints).centered().style(theme.hint))
+                .border_type(Rounded)
+                .border_style(theme.border),
+        );
+
+    frame.render_stateful_widget(list, area, &mut list_state);
+}
+
+/// Popup listing the choices of a menu, with the selected one highlighted.
+pub fn render_menu(frame: &mut Frame, menu: &Menu, theme: &Theme) {
+    let items: Vec<ListItem> = menu.labels().map(ListItem::new).collect();
+    let width = menu
+        .labels()
+        .map(|label| Line::from(label).width() as u16)
+        .max()
+        .unwrap_or(0)
+        .max(menu.title.len() as u16)
+        + 8;
+    let area = centered_rect(frame.area(), width, items.len() as u16 + 2);
+    let mut list_state = ListState::default();
+    list_state.select(Some(menu.selected));
+
+    let list = List::new(items)
+        .highlight_style(theme.queue_selection)
+        .highlight_symbol("⏵ ")
+        .block(
+            Block::bordered()
+                .border_type(Rounded)
+                .border_style(theme.border)
+                .title(format!(" {} ", menu.title))
                 .title_bottom(
-                    Line::from(" ↑↓ Navigate • ENTER to play ")
+                    Line::from(" ENTER select • ESC cancel ")
                         .centered()
-                        .style(Style::default().fg(Color::DarkGray)),
-                )
-                .border_type(Rounded),
+                        .style(theme.hint),
+                ),
         );
-
+    frame.render_widget(Clear, area);
     frame.render_stateful_widget(list, area, &mut list_state);
 }
 
+/// Popup with the text typed into a prompt.
+pub fn render_prompt(frame: &mut Frame, prompt: &Prompt, theme: &Theme) {
+    let area = centered_rect(frame.area(), 70, 3);
+    let width = area.width.saturating_sub(3) as usize;
+    // Keep the end of long inputs visible
+    let skip = prompt.input.chars().count().saturating_sub(width);
+    let input: String = prompt.input.chars().skip(skip).collect();
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(format!("{input}▏")).block(
+            Block::bordered()
+                .border_type(Rounded)
+                .border_style(theme.border)
+                .title(format!(" {} ", prompt.title))
+                .title_bottom(
+                    Line::from(" ENTER confirm • ESC cancel ")
+                        .centered()
+                        .style(theme.hint),
+                ),
+        ),
+        area,
+    );
+}
+
+/// Popup with the settings of the alarm being created or edited.
+pub fn render_alarm_editor(
+    frame: &mut Frame,
+    editor: &AlarmEditor,
+    keymap: &Keymap,
+    theme: &Theme,
+) {
+    let lines: Vec<Line> = AlarmField::ALL
+        .iter()
+        .map(|&field| {
+            let line = Line::from(format!(
+                "  {:<8}  ‹ {} ›",
+                field.label(),
+                editor.value(field)
+            ));
+            if field == editor.field {
+                line.style(theme.queue_selection)
+            } else {
+                line
+            }
+        })
+        .collect();
+
+    let title = if editor.is_new() {
+        " New alarm "
+    } else {
+        " Edit alarm "
+    };
+    let hints = keymap.hints(
+        KeyContext::AlarmEditor,
+        &[
+            (&[KeyAction::Up, KeyAction::Down], "field"),
+            (&[KeyAction::Decrease, KeyAction::Increase], "change"),
+            (&[KeyAction::Save], "save"),
+            (&[KeyAction::Cancel], "cancel"),
+        ],
+    );
+    let area = centered_rect(frame.area(), 52, lines.len() as u16 + 2);
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines).block(
+            Block::bordered()
+                .border_type(Rounded)
+                .border_style(theme.border)
+                .title(title)
+                .title_bottom(Line::from(hints).centered().style(theme.hint)),
+        ),
+        area,
+    );
+}
+
 fn format_duration(secs: u32) -> String {
     let minutes = secs / 60;
     let seconds = secs % 60;